@@ -1,18 +1,75 @@
 use anyhow::*;
-use fs_extra::copy_items;
-use fs_extra::dir::CopyOptions;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use glob::glob;
 use std::env;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
 
+// Keep in sync with the reader in src/utils/file_utils.rs
+const PAK_MAGIC: &[u8; 4] = b"SRPK";
+const PAK_VERSION: u32 = 1;
+const PAK_NAME: &str = "assets.pak";
+
+struct PakEntry {
+    path: String,
+    size: u64,
+    crc: u32,
+    data: Vec<u8>,
+}
+
+/// Packs everything in /res/ into a single archive laid out as
+/// magic, version, entry count, then one index record per file
+/// (path length, path, offset, compressed size, size, crc32)
+/// followed by the deflate compressed file contents.
 fn main() -> Result<()> {
     // This tells cargo to rerun this script if something in /res/ changes.
-    println!("cargo:rerun-if-changed=res/*");
+    println!("cargo:rerun-if-changed=res");
+
+    let mut entries = Vec::new();
+    for path in glob("res/**/*")? {
+        let path = path?;
+        if !path.is_file() {
+            continue;
+        }
+
+        let contents = std::fs::read(&path)?;
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(&contents)?;
+        entries.push(PakEntry {
+            path: path
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/"),
+            size: contents.len() as u64,
+            crc: crc32fast::hash(&contents),
+            data: encoder.finish()?,
+        });
+    }
 
     let out_dir = env::var("OUT_DIR")?;
-    let mut copy_options = CopyOptions::new();
-    copy_options.overwrite = true;
-    let mut paths_to_copy = Vec::new();
-    paths_to_copy.push("res/");
-    copy_items(&paths_to_copy, out_dir, &copy_options)?;
+    let mut writer = BufWriter::new(File::create(Path::new(&out_dir).join(PAK_NAME))?);
+    writer.write_all(PAK_MAGIC)?;
+    writer.write_all(&PAK_VERSION.to_le_bytes())?;
+    writer.write_all(&(entries.len() as u32).to_le_bytes())?;
+
+    let index_size: usize = entries.iter().map(|e| 2 + e.path.len() + 8 * 3 + 4).sum();
+    let mut offset = (PAK_MAGIC.len() + 4 + 4 + index_size) as u64;
+    for entry in &entries {
+        writer.write_all(&(entry.path.len() as u16).to_le_bytes())?;
+        writer.write_all(entry.path.as_bytes())?;
+        writer.write_all(&offset.to_le_bytes())?;
+        writer.write_all(&(entry.data.len() as u64).to_le_bytes())?;
+        writer.write_all(&entry.size.to_le_bytes())?;
+        writer.write_all(&entry.crc.to_le_bytes())?;
+        offset += entry.data.len() as u64;
+    }
+    for entry in &entries {
+        writer.write_all(&entry.data)?;
+    }
+    writer.flush()?;
 
     Ok(())
 }
@@ -0,0 +1,10 @@
+#version 150
+precision mediump float;
+
+in vec2 position;
+out vec2 vUv;
+
+void main() {
+  vUv = (position + vec2(1.0)) / 2.0;
+  gl_Position = vec4(position, 1.0, 1.0);
+}
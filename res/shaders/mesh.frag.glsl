@@ -0,0 +1,39 @@
+#version 330
+precision mediump float;
+
+in lowp vec2 uv;
+in vec4 VertexColor;
+in vec3 Normal;
+in vec3 FragPos;
+in float LogDepth;
+
+out vec4 diffuseColor;
+
+uniform vec3 LightColor;
+uniform vec3 ObjectColor;
+uniform sampler2D Texture;
+uniform float DepthCoefficient;
+
+void main() {
+    float ambientStrength = 0.1;
+    vec3 ambient = ambientStrength * LightColor;
+
+    //vec3 result = ambient * ObjectColor;
+    
+    vec3 norm = normalize(Normal);
+    vec3 lightDir = vec3(-0.25, 0.25, 0.5);
+
+    float diff = max(dot(norm, lightDir), 0.0);
+    vec3 diffuse = diff * LightColor;
+
+    vec3 result = (ambient + diffuse) * ObjectColor;
+    //FragColor = vec4(result, 1.0);
+    vec2 updatedUV = vec2(uv.x, 1.0 - uv.y);
+
+    diffuseColor = vec4(result, 1.0) * VertexColor * texture(Texture, updatedUV);
+
+#ifdef LOG_DEPTH
+    // Only written for logarithmic depth, since writing it at all turns off early depth testing
+    gl_FragDepth = log2(LogDepth) * DepthCoefficient * 0.5;
+#endif
+}
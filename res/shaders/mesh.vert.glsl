@@ -0,0 +1,30 @@
+#version 330
+precision mediump float;
+
+in vec3 position;
+in vec2 texcoord;
+in vec4 color0;
+in vec3 normal;
+
+out lowp vec2 uv;
+out vec4 VertexColor;
+out vec3 Normal;
+out vec3 FragPos;
+out float LogDepth;
+
+uniform mat4 Model;
+uniform mat4 Projection;
+uniform float DepthCoefficient;
+
+void main() {
+    gl_Position = Projection * Model * vec4(position, 1);
+#ifdef LOG_DEPTH
+    // Logarithmic depth, written again per fragment to avoid interpolation errors
+    LogDepth = 1.0 + gl_Position.w;
+    gl_Position.z = (log2(max(1e-6, LogDepth)) * DepthCoefficient - 1.0) * gl_Position.w;
+#endif
+    uv = texcoord;
+    VertexColor = color0;
+    Normal = normal;
+    FragPos = vec3(Model * vec4(position, 1.0));
+}
@@ -1,101 +1,411 @@
 use bevy_ecs::{
-    query::With,
+    event::{Event, EventReader},
     system::{Query, Res, ResMut, Resource},
 };
-use macroquad::{
-    camera::{set_camera, set_default_camera, Camera3D},
-    color::{GRAY, ORANGE, PURPLE, RED, WHITE, YELLOW},
-    math::{vec3, Vec3},
-    models::{draw_cube, draw_sphere},
-    text::draw_text,
-    time::get_frame_time,
-};
+use macroquad::prelude::*;
 
 use crate::{
-    player::player::Player, transform::transform::Transform, utils::input_utils::MouseInput,
+    camera::spring::Spring,
+    input::input_map::{Action, Actions, InputMap},
+    physics::rigid_body::RigidBody,
+    player::player::Player,
+    time::time::Time,
+    transform::transform::{PreviousTransform, Transform},
 };
 
-const LOOK_SPEED: f32 = 0.1;
+/// Pilot's eye position along the ship's right, up and forward axes.
+const COCKPIT_OFFSET: Vec3 = Vec3::new(0., 1., 2.);
+const ORBIT_DISTANCE: f32 = 40.;
+const ORBIT_ZOOM: f32 = 0.1;
+/// Radians turned per pixel of mouse movement in the orbit and free-fly cameras.
+const LOOK_SENSITIVITY: f32 = 0.003;
+/// Radians per second turned with a stick held fully over.
+const LOOK_RATE: f32 = 2.;
+const FREE_FLY_SPEED: f32 = 100.;
+const FREE_FLY_BOOST: f32 = 5.;
+/// Longest frame the chase springs are stepped over in one go.
+const MAX_SPRING_STEP: f32 = 1. / 30.;
+/// Distance the ship can move in a frame before the chase camera snaps instead of following.
+const TELEPORT_DISTANCE: f32 = 100.;
+/// Radians of bank per radian per second of yaw, up to `MAX_BANK`.
+const BANK_PER_YAW_RATE: f32 = 0.2;
+const MAX_BANK: f32 = 0.4;
+/// Trauma lost per second.
+const TRAUMA_DECAY: f32 = 1.5;
+const MAX_SHAKE_ANGLE: f32 = 0.08;
+const SHAKE_FREQUENCY: f32 = 25.;
+const FOV: f32 = 60. * std::f32::consts::PI / 180.;
+const SPEED_FOV: f32 = 10. * std::f32::consts::PI / 180.;
+const BOOST_FOV: f32 = 10. * std::f32::consts::PI / 180.;
+/// How quickly the field of view follows speed and boost changes.
+const FOV_RESPONSE: f32 = 4.;
+/// Far enough to keep the largest asteroids in view, near enough to keep depth precise.
+const Z_NEAR: f32 = 0.5;
+const Z_FAR: f32 = 50000.;
+
+/// How depth is written by the mesh pipeline.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DepthMode {
+    /// Regular perspective depth, precise up close but starting to fight far away.
+    Standard,
+    /// Depth spread logarithmically up to the far plane, keeping precision across huge scenes.
+    /// Only the mesh pipeline writes it, so everything in the world has to be drawn through it.
+    Logarithmic,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CameraMode {
+    /// Follows behind the ship at `camera_offset`, lagging behind its movement and turns.
+    Chase,
+    /// Looks out from the pilot's seat.
+    Cockpit,
+    /// Circles the ship, steered with the mouse and zoomed with the wheel.
+    Orbit { yaw: f32, pitch: f32, distance: f32 },
+    /// Detached from the ship for debugging, flown with the mouse and movement keys while the ship
+    /// is left alone.
+    FreeFly { yaw: f32, pitch: f32 },
+}
+
+impl CameraMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            CameraMode::Chase => "Chase",
+            CameraMode::Cockpit => "Cockpit",
+            CameraMode::Orbit { .. } => "Orbit",
+            CameraMode::FreeFly { .. } => "Free fly",
+        }
+    }
+
+    /// Whether the pilot's input should still fly the ship.
+    pub fn controls_ship(self) -> bool {
+        !matches!(self, CameraMode::FreeFly { .. })
+    }
+
+    /// Whether the mouse moves the camera rather than steering the ship.
+    pub fn uses_mouse(self) -> bool {
+        matches!(self, CameraMode::Orbit { .. } | CameraMode::FreeFly { .. })
+    }
+}
+
+/// Adds trauma to the camera, shaking it more the closer it gets to 1. Trauma wears off over
+/// time, and the shake grows with its square so small knocks stay subtle.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct CameraShake {
+    pub trauma: f32,
+}
 
+/// Camera following the player's ship in one of several modes.
 #[derive(Resource)]
 pub struct CameraState {
-    camera_offset: Vec3,              // = vec3(0., 0., -50.);
-    pub(crate) camera_position: Vec3, // = player.get_pos() + camera_offset;
+    pub mode: CameraMode,
+    /// Offset of the chase camera from the ship along its own right, up and forward axes.
+    camera_offset: Vec3,
+    pub position_spring: Spring,
+    pub rotation_spring: Spring,
+    /// Vertical field of view in radians while flying slowly.
+    pub fov: f32,
+    /// Field of view added at the ship's top speed and while boosting, for a sense of speed.
+    pub speed_fov: f32,
+    pub boost_fov: f32,
+    pub z_near: f32,
+    pub z_far: f32,
+    pub depth: DepthMode,
+    current_fov: f32,
+    pub(crate) camera_position: Vec3,
+    velocity: Vec3,
+    /// Orientation of the chase camera before banking and shake are added.
+    rotation: Quat,
+    angular_velocity: Vec3,
+    /// Where the chase camera was headed last frame, to tell how fast its target is moving.
+    previous_target: Option<(Vec3, Quat)>,
+    /// Extra bank into turns in radians, on top of the ship's own roll.
+    roll: f32,
+    roll_velocity: f32,
+    trauma: f32,
+    /// Seconds of shake so far, kept on the frame clock so replays shake the same way.
+    shake_time: f32,
     pub(crate) front: Vec3,
     pub(crate) right: Vec3,
     pub(crate) up: Vec3,
-    yaw: f32,
-    pitch: f32,
-    roll: f32,
 }
 
 impl CameraState {
-    pub fn new(
-        camera_offset: Vec3,
-        camera_position: Vec3,
-        front: Vec3,
-        right: Vec3,
-        up: Vec3,
-        yaw: f32,
-        pitch: f32,
-        roll: f32,
-    ) -> Self {
+    pub fn new(camera_offset: Vec3) -> Self {
         Self {
+            mode: CameraMode::Chase,
             camera_offset,
-            camera_position,
-            front,
-            right,
-            up,
-            yaw,
-            pitch,
-            roll,
+            position_spring: Spring::critically_damped(60.),
+            rotation_spring: Spring::critically_damped(120.),
+            fov: FOV,
+            speed_fov: SPEED_FOV,
+            boost_fov: BOOST_FOV,
+            z_near: Z_NEAR,
+            z_far: Z_FAR,
+            depth: DepthMode::Standard,
+            current_fov: FOV,
+            camera_position: Vec3::ZERO,
+            velocity: Vec3::ZERO,
+            rotation: Quat::IDENTITY,
+            angular_velocity: Vec3::ZERO,
+            previous_target: None,
+            roll: 0.,
+            roll_velocity: 0.,
+            trauma: 0.,
+            shake_time: 0.,
+            front: Vec3::Z,
+            right: Vec3::NEG_X,
+            up: Vec3::Y,
+        }
+    }
+
+    pub fn with_springs(mut self, position: Spring, rotation: Spring) -> Self {
+        self.position_spring = position;
+        self.rotation_spring = rotation;
+        self
+    }
+
+    /// Switches to the next mode, starting the free-fly camera from wherever the camera is now.
+    pub fn next_mode(&mut self) {
+        self.mode = match self.mode {
+            CameraMode::Chase => CameraMode::Cockpit,
+            CameraMode::Cockpit => CameraMode::Orbit {
+                yaw: 0.,
+                pitch: 0.3,
+                distance: ORBIT_DISTANCE,
+            },
+            CameraMode::Orbit { .. } => CameraMode::FreeFly {
+                yaw: self.front.x.atan2(self.front.z),
+                pitch: (-self.front.y).asin(),
+            },
+            CameraMode::FreeFly { .. } => CameraMode::Chase,
+        };
+        // Start the chase camera over instead of springing in from wherever it was left
+        self.previous_target = None;
+    }
+
+    /// Scale of the logarithmic depth written by the mesh pipeline, zero for standard depth.
+    pub fn depth_coefficient(&self) -> f32 {
+        match self.depth {
+            DepthMode::Standard => 0.,
+            DepthMode::Logarithmic => 2. / (self.z_far + 1.).log2(),
         }
     }
+
+    fn look(&mut self, rotation: Quat) {
+        self.front = rotation * Vec3::Z;
+        self.up = rotation * Vec3::Y;
+        self.right = self.front.cross(self.up);
+    }
+
+    /// Springs the chase camera towards its spot behind the ship, banking into turns.
+    fn chase(&mut self, transform: &Transform, dt: f32) -> Quat {
+        let target = transform.position + transform.rotation * self.camera_offset;
+        let (target_velocity, target_angular_velocity) = match self.previous_target {
+            Some((position, rotation))
+                if dt > 0. && position.distance(target) < TELEPORT_DISTANCE =>
+            {
+                (
+                    (target - position) / dt,
+                    rotation_between(rotation, transform.rotation) / dt,
+                )
+            }
+            _ => {
+                // First frame or the ship jumped, so snap into place
+                self.camera_position = target;
+                self.velocity = Vec3::ZERO;
+                self.rotation = transform.rotation;
+                self.angular_velocity = Vec3::ZERO;
+                (Vec3::ZERO, Vec3::ZERO)
+            }
+        };
+        self.previous_target = Some((target, transform.rotation));
+
+        // Large steps would make a stiff spring blow up
+        let dt = dt.min(MAX_SPRING_STEP);
+        let (mut position, mut velocity) = (self.camera_position, self.velocity);
+        self.position_spring
+            .step(&mut position, &mut velocity, target, target_velocity, dt);
+        (self.camera_position, self.velocity) = (position, velocity);
+
+        let mut turn = Vec3::ZERO;
+        let mut angular_velocity = self.angular_velocity;
+        self.rotation_spring.step(
+            &mut turn,
+            &mut angular_velocity,
+            rotation_between(self.rotation, transform.rotation),
+            target_angular_velocity,
+            dt,
+        );
+        self.angular_velocity = angular_velocity;
+        self.rotation = (Quat::from_scaled_axis(turn) * self.rotation).normalize();
+
+        let yaw_rate = target_angular_velocity.dot(transform.up());
+        let bank = (-yaw_rate * BANK_PER_YAW_RATE).clamp(-MAX_BANK, MAX_BANK);
+        let (mut roll, mut roll_velocity) = (self.roll, self.roll_velocity);
+        self.rotation_spring
+            .step(&mut roll, &mut roll_velocity, bank, 0., dt);
+        (self.roll, self.roll_velocity) = (roll, roll_velocity);
+
+        self.rotation * Quat::from_rotation_z(self.roll)
+    }
+
+    /// Random looking but smooth rotation scaled by the current trauma.
+    fn shake(&self) -> Quat {
+        let shake = self.trauma * self.trauma * MAX_SHAKE_ANGLE;
+        let t = self.shake_time * SHAKE_FREQUENCY;
+        let noise = |seed: f32| ((t + seed).sin() + (t * 2.3 + seed * 1.7).sin()) / 2.;
+        Quat::from_euler(
+            EulerRot::YXZ,
+            noise(0.) * shake,
+            noise(10.) * shake,
+            noise(20.) * shake,
+        )
+    }
+}
+
+/// Smallest rotation taking `from` to `to`, as an axis scaled by the angle.
+fn rotation_between(from: Quat, to: Quat) -> Vec3 {
+    let difference = to * from.inverse();
+    if difference.w < 0. {
+        (-difference).to_scaled_axis()
+    } else {
+        difference.to_scaled_axis()
+    }
 }
 
 pub fn reset_camera(camera: Res<CameraState>) {
     set_default_camera();
 }
 
+/// Switches camera modes and moves the cameras that are driven by the mouse and keys rather than
+/// the ship.
+pub fn camera_input(mut camera: ResMut<CameraState>, actions: Res<Actions>, time: Res<Time>) {
+    if actions.pressed(Action::CameraMode) {
+        camera.next_mode();
+    }
+    if actions.pressed(Action::DepthMode) {
+        camera.depth = match camera.depth {
+            DepthMode::Standard => DepthMode::Logarithmic,
+            DepthMode::Logarithmic => DepthMode::Standard,
+        };
+    }
+
+    let camera = &mut *camera;
+    let look = vec2(actions.motion(Action::Yaw), actions.motion(Action::Pitch)) * LOOK_SENSITIVITY
+        + vec2(actions.value(Action::Yaw), actions.value(Action::Pitch)) * LOOK_RATE * time.delta();
+    match &mut camera.mode {
+        CameraMode::Orbit {
+            yaw,
+            pitch,
+            distance,
+        } => {
+            *yaw += look.x;
+            *pitch = (*pitch - look.y).clamp(-1.5, 1.5);
+            let scroll = actions.motion(Action::Zoom);
+            if scroll != 0. {
+                *distance *= 1. - scroll.signum() * ORBIT_ZOOM;
+            }
+        }
+        CameraMode::FreeFly { yaw, pitch } => {
+            *yaw += look.x;
+            *pitch = (*pitch - look.y).clamp(-1.5, 1.5);
+
+            let movement = vec3(
+                actions.axis(Action::StrafeLeft, Action::StrafeRight),
+                actions.axis(Action::StrafeDown, Action::StrafeUp),
+                actions.axis(Action::ThrustBackward, Action::ThrustForward),
+            );
+            let boost = if actions.down(Action::Afterburner) {
+                FREE_FLY_BOOST
+            } else {
+                1.
+            };
+            let velocity =
+                (camera.right * movement.x + camera.up * movement.y + camera.front * movement.z)
+                    * FREE_FLY_SPEED
+                    * boost;
+            camera.camera_position += velocity * time.delta();
+        }
+        _ => {}
+    }
+}
+
 pub fn update_camera(
-    query: Query<&Transform, With<Player>>,
+    query: Query<(&Player, &RigidBody, &Transform, Option<&PreviousTransform>)>,
     mut camera: ResMut<CameraState>,
-    mouse_input: Res<MouseInput>,
+    mut shakes: EventReader<CameraShake>,
+    time: Res<Time>,
 ) {
-    camera.yaw += mouse_input.mouse_delta.x * get_frame_time() * LOOK_SPEED;
-    camera.pitch += mouse_input.mouse_delta.y * get_frame_time() * -LOOK_SPEED;
+    let (player, body, transform, previous) = query.get_single().unwrap();
+    let transform = transform.interpolated(previous, time.alpha());
 
-    camera.pitch = if camera.pitch > 1.5 {
-        1.5
-    } else {
-        camera.pitch
-    };
-    camera.pitch = if camera.pitch < -1.5 {
-        -1.5
+    // Widen the view with speed, easing into it so boosting doesn't snap
+    let fov = if camera.mode.controls_ship() {
+        let speed = (body.velocity.length() / player.max_speed()).min(1.);
+        camera.fov
+            + camera.speed_fov * speed
+            + camera.boost_fov * player.afterburning() as i32 as f32
     } else {
-        camera.pitch
+        camera.fov
     };
+    camera.current_fov = camera
+        .current_fov
+        .lerp(fov, 1. - (-FOV_RESPONSE * time.delta()).exp());
 
-    camera.front = vec3(
-        camera.yaw.cos() * camera.pitch.cos(),
-        camera.pitch.sin(),
-        camera.yaw.sin() * camera.pitch.cos(),
-    )
-    .normalize();
-
-    camera.right = camera.front.cross(Vec3::Y).normalize();
-    camera.up = camera.right.cross(camera.front).normalize();
+    for shake in shakes.read() {
+        camera.trauma = (camera.trauma + shake.trauma).min(1.);
+    }
+    camera.trauma = (camera.trauma - TRAUMA_DECAY * time.delta()).max(0.);
+    camera.shake_time += time.delta();
 
-    let transform = query.get_single().unwrap();
-    camera.camera_position = transform.position
-        + (camera.front * camera.camera_offset.z)
-        + (-camera.right * camera.camera_offset.x)
-        + (camera.up * camera.camera_offset.y);
+    let rotation = match camera.mode {
+        CameraMode::Chase => camera.chase(&transform, time.delta()),
+        CameraMode::Cockpit => {
+            camera.camera_position = transform.position + transform.rotation * COCKPIT_OFFSET;
+            transform.rotation
+        }
+        CameraMode::Orbit {
+            yaw,
+            pitch,
+            distance,
+        } => {
+            let rotation = transform.rotation * Quat::from_euler(EulerRot::YXZ, yaw, pitch, 0.);
+            camera.camera_position = transform.position - rotation * Vec3::Z * distance;
+            rotation
+        }
+        CameraMode::FreeFly { yaw, pitch } => Quat::from_euler(EulerRot::YXZ, yaw, pitch, 0.),
+    };
+    let shake = if camera.mode.controls_ship() {
+        camera.shake()
+    } else {
+        Quat::IDENTITY
+    };
+    camera.look(rotation * shake);
 
     set_camera(&Camera3D {
         position: camera.camera_position,
         up: camera.up,
         target: camera.camera_position + camera.front * 10.,
+        fovy: camera.current_fov,
+        z_near: camera.z_near,
+        z_far: camera.z_far,
         ..Default::default()
     });
 }
+
+pub fn draw_camera_hud(camera: Res<CameraState>, input_map: Res<InputMap>) {
+    draw_text(
+        format!(
+            "Camera <{}>: {}  Depth <{}>: {:?}",
+            input_map.label(Action::CameraMode),
+            camera.mode.name(),
+            input_map.label(Action::DepthMode),
+            camera.depth
+        )
+        .as_str(),
+        10.0,
+        48.0 + 48.0 + 120.0,
+        30.0,
+        WHITE,
+    );
+}
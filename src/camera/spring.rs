@@ -0,0 +1,31 @@
+use std::ops::{Add, Mul, Sub};
+
+/// Damped spring pulling a value towards a moving target, used to smooth the camera.
+#[derive(Clone, Copy, Debug)]
+pub struct Spring {
+    pub stiffness: f32,
+    pub damping: f32,
+}
+
+impl Spring {
+    /// Spring that settles as quickly as possible without overshooting.
+    pub fn critically_damped(stiffness: f32) -> Self {
+        Self {
+            stiffness,
+            damping: 2. * stiffness.sqrt(),
+        }
+    }
+
+    /// Advances `value` and its `velocity` by one semi-implicit Euler step towards `target`.
+    /// Matching the target's own velocity keeps the spring from trailing behind a target moving
+    /// at a constant speed.
+    pub fn step<T>(&self, value: &mut T, velocity: &mut T, target: T, target_velocity: T, dt: f32)
+    where
+        T: Copy + Add<Output = T> + Sub<Output = T> + Mul<f32, Output = T>,
+    {
+        let acceleration =
+            (target - *value) * self.stiffness + (target_velocity - *velocity) * self.damping;
+        *velocity = *velocity + acceleration * dt;
+        *value = *value + *velocity * dt;
+    }
+}
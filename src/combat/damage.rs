@@ -0,0 +1,158 @@
+use std::collections::HashSet;
+
+use bevy_ecs::{
+    component::Component,
+    entity::Entity,
+    event::{Event, EventReader, EventWriter},
+    query::Has,
+    system::{Commands, Query, Res},
+};
+
+use crate::{
+    camera::camera::CameraShake,
+    physics::collision::CollisionEvent,
+    player::{energy::EnergySystem, player::Player},
+    projectiles::bullets::{Bullet, Owner},
+    time::time::Time,
+};
+
+/// Camera trauma per point of damage taken by the player.
+const HIT_TRAUMA: f32 = 0.02;
+
+#[derive(Component)]
+pub struct Health {
+    current: f32,
+    max: f32,
+}
+
+impl Health {
+    pub fn new(max: f32) -> Self {
+        Self { current: max, max }
+    }
+
+    pub fn current(&self) -> f32 {
+        self.current
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.current <= 0.
+    }
+
+    pub fn restore(&mut self) {
+        self.current = self.max;
+    }
+}
+
+/// Absorbs damage before it reaches `Health`, recharging once the entity hasn't been hit for a
+/// while.
+#[derive(Component)]
+pub struct Shield {
+    current: f32,
+    max: f32,
+    /// Points recharged per second.
+    regen_rate: f32,
+    /// Seconds without being hit before recharging starts.
+    regen_delay: f32,
+    since_hit: f32,
+}
+
+impl Shield {
+    pub fn new(max: f32, regen_rate: f32, regen_delay: f32) -> Self {
+        Self {
+            current: max,
+            max,
+            regen_rate,
+            regen_delay,
+            since_hit: regen_delay,
+        }
+    }
+
+    pub fn current(&self) -> f32 {
+        self.current
+    }
+
+    pub fn restore(&mut self) {
+        self.current = self.max;
+        self.since_hit = self.regen_delay;
+    }
+}
+
+#[derive(Event, Clone, Copy, Debug)]
+pub struct DamageEvent {
+    pub target: Entity,
+    pub amount: f32,
+}
+
+/// Turns bullets overlapping anything but their owner or other bullets into damage, using the
+/// bullet up.
+pub fn projectile_hits(
+    mut commands: Commands,
+    mut collisions: EventReader<CollisionEvent>,
+    bullets: Query<(&Bullet, &Owner)>,
+    mut damage: EventWriter<DamageEvent>,
+) {
+    let mut spent = HashSet::new();
+    for event in collisions.read() {
+        for (entity, target) in [(event.a, event.b), (event.b, event.a)] {
+            let Ok((bullet, owner)) = bullets.get(entity) else {
+                continue;
+            };
+            if owner.0 == target || bullets.contains(target) || !spent.insert(entity) {
+                continue;
+            }
+            damage.send(DamageEvent {
+                target,
+                amount: bullet.damage(),
+            });
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+pub fn apply_damage(
+    mut events: EventReader<DamageEvent>,
+    mut query: Query<(&mut Health, Option<&mut Shield>, Has<Player>)>,
+    mut shakes: EventWriter<CameraShake>,
+) {
+    for event in events.read() {
+        let Ok((mut health, shield, is_player)) = query.get_mut(event.target) else {
+            continue;
+        };
+        if is_player {
+            shakes.send(CameraShake {
+                trauma: event.amount * HIT_TRAUMA,
+            });
+        }
+
+        let mut amount = event.amount;
+        if let Some(mut shield) = shield {
+            shield.since_hit = 0.;
+            let absorbed = amount.min(shield.current);
+            shield.current -= absorbed;
+            amount -= absorbed;
+        }
+        health.current = (health.current - amount).max(0.);
+    }
+}
+
+/// Recharges shields that haven't been hit recently. The player's shields are fed from the
+/// shields capacitor, so they charge faster with more pips and stall when it runs dry.
+pub fn regenerate_shields(mut query: Query<(&mut Shield, Option<&mut Player>)>, time: Res<Time>) {
+    let delta = time.fixed_delta();
+    for (mut shield, player) in query.iter_mut() {
+        shield.since_hit += delta;
+        if shield.since_hit < shield.regen_delay || shield.current >= shield.max {
+            continue;
+        }
+
+        let mut amount = shield.regen_rate * delta;
+        if let Some(mut player) = player {
+            amount *= player.energy.boost(EnergySystem::Shields);
+            amount = amount.min(shield.max - shield.current);
+            if !player.energy.drain(EnergySystem::Shields, amount) {
+                continue;
+            }
+        }
+        shield.current = (shield.current + amount).min(shield.max);
+    }
+}
@@ -0,0 +1,206 @@
+use bevy_ecs::{
+    bundle::Bundle,
+    component::Component,
+    entity::Entity,
+    event::EventWriter,
+    query::{With, Without},
+    system::{Commands, Query, Res},
+};
+use macroquad::prelude::*;
+
+use crate::{
+    camera::camera::CameraShake,
+    combat::damage::{Health, Shield},
+    physics::{collider::Collider, rigid_body::RigidBody},
+    player::player::Player,
+    time::time::Time,
+    transform::transform::{PreviousTransform, Transform},
+    utils::mesh_utils::Model,
+};
+
+const DEBRIS_PIECES: usize = 6;
+const DEBRIS_LIFETIME: f32 = 4.;
+const DEBRIS_SPEED: f32 = 20.;
+const RESPAWN_DELAY: f32 = 3.;
+/// Camera trauma from an explosion right next to the player, fading out to nothing at
+/// `EXPLOSION_SHAKE_RANGE`.
+const EXPLOSION_TRAUMA: f32 = 0.6;
+const EXPLOSION_SHAKE_RANGE: f32 = 500.;
+
+/// Piece of a destroyed entity, drifting away until it disappears.
+#[derive(Component)]
+pub struct Debris {
+    lifetime: f32,
+}
+
+/// Where the player's ship comes back after being destroyed.
+#[derive(Component)]
+pub struct SpawnPoint(pub Transform);
+
+/// Marks a destroyed player ship while it waits to respawn, holding the parts taken off it so
+/// it can't be seen or hit in the meantime.
+#[derive(Component)]
+pub struct Respawning {
+    timer: f32,
+    model: Model,
+    collider: Collider,
+}
+
+impl Respawning {
+    pub fn remaining(&self) -> f32 {
+        self.timer
+    }
+
+    /// The hidden model and collider, for swapping in a reloaded version.
+    pub fn parts_mut(&mut self) -> (&mut Model, &mut Collider) {
+        (&mut self.model, &mut self.collider)
+    }
+}
+
+fn spawn_debris(commands: &mut Commands, transform: &Transform, velocity: Vec3, model: &Model) {
+    let random_direction = || {
+        vec3(
+            rand::gen_range(-1., 1.),
+            rand::gen_range(-1., 1.),
+            rand::gen_range(-1., 1.),
+        )
+        .normalize_or_zero()
+    };
+
+    for _ in 0..DEBRIS_PIECES {
+        let direction = random_direction();
+        let scale = transform.scale * rand::gen_range(0.15, 0.35);
+        let mut body = RigidBody::new(1., scale.max_element())
+            .with_velocity(velocity + direction * DEBRIS_SPEED);
+        body.angular_velocity = random_direction() * 2.;
+
+        commands.spawn((
+            Debris {
+                lifetime: DEBRIS_LIFETIME * rand::gen_range(0.5, 1.),
+            },
+            model.clone(),
+            body,
+            Transform {
+                position: transform.position + direction * transform.scale.max_element() * 0.5,
+                scale,
+                rotation: transform.rotation,
+            },
+        ));
+    }
+}
+
+/// Breaks anything but the player into debris once it runs out of health.
+pub fn destroy_dead(
+    mut commands: Commands,
+    query: Query<(Entity, &Health, &Transform, &RigidBody, &Model), Without<Player>>,
+    players: Query<&Transform, With<Player>>,
+    mut shakes: EventWriter<CameraShake>,
+) {
+    for (entity, health, transform, body, model) in query.iter() {
+        if !health.is_dead() {
+            continue;
+        }
+        spawn_debris(&mut commands, transform, body.velocity, model);
+        commands.entity(entity).despawn();
+
+        for player in players.iter() {
+            let distance = player.position.distance(transform.position);
+            shakes.send(CameraShake {
+                trauma: EXPLOSION_TRAUMA * (1. - distance / EXPLOSION_SHAKE_RANGE).max(0.),
+            });
+        }
+    }
+}
+
+pub fn update_debris(
+    mut commands: Commands,
+    mut query: Query<(Entity, &mut Debris)>,
+    time: Res<Time>,
+) {
+    for (entity, mut debris) in query.iter_mut() {
+        debris.lifetime -= time.fixed_delta();
+        if debris.lifetime <= 0. {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+type AlivePlayer = (With<Player>, Without<Respawning>);
+
+/// Blows up the player's ship, hiding it until it respawns.
+pub fn kill_player(
+    mut commands: Commands,
+    mut query: Query<
+        (
+            Entity,
+            &Health,
+            &Transform,
+            &mut RigidBody,
+            &Model,
+            &Collider,
+        ),
+        AlivePlayer,
+    >,
+    mut shakes: EventWriter<CameraShake>,
+) {
+    for (entity, health, transform, mut body, model, collider) in query.iter_mut() {
+        if !health.is_dead() {
+            continue;
+        }
+        spawn_debris(&mut commands, transform, body.velocity, model);
+        shakes.send(CameraShake { trauma: 1. });
+
+        body.velocity = Vec3::ZERO;
+        body.angular_velocity = Vec3::ZERO;
+        body.force = Vec3::ZERO;
+        body.torque = Vec3::ZERO;
+        commands
+            .entity(entity)
+            .remove::<(Model, Collider)>()
+            .insert(Respawning {
+                timer: RESPAWN_DELAY,
+                model: model.clone(),
+                collider: collider.clone(),
+            });
+    }
+}
+
+type RespawningPlayer<'a> = (
+    Entity,
+    &'a mut Respawning,
+    &'a SpawnPoint,
+    &'a mut Health,
+    Option<&'a mut Shield>,
+    &'a mut Transform,
+);
+
+pub fn respawn_player(mut commands: Commands, mut query: Query<RespawningPlayer>, time: Res<Time>) {
+    for (entity, mut respawning, spawn, mut health, shield, mut transform) in query.iter_mut() {
+        respawning.timer -= time.fixed_delta();
+        if respawning.timer > 0. {
+            continue;
+        }
+
+        *transform = spawn.0;
+        health.restore();
+        if let Some(mut shield) = shield {
+            shield.restore();
+        }
+        commands
+            .entity(entity)
+            .remove::<Respawning>()
+            .insert(RespawnedBundle {
+                model: respawning.model.clone(),
+                collider: respawning.collider.clone(),
+                // Don't interpolate the jump back to the spawn point
+                previous: PreviousTransform(spawn.0),
+            });
+    }
+}
+
+#[derive(Bundle)]
+struct RespawnedBundle {
+    model: Model,
+    collider: Collider,
+    previous: PreviousTransform,
+}
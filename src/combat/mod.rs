@@ -0,0 +1,2 @@
+pub mod damage;
+pub mod destruction;
@@ -0,0 +1,142 @@
+use gilrs::{Axis, Button, EventType, Gilrs, GilrsBuilder};
+
+/// Names of the gamepad buttons that can be bound, as written in the config file. Only devices
+/// with a gamepad mapping have their controls named, which flight sticks and throttles often
+/// lack. Their axes can still be bound by raw code, see `RAW_AXIS_PREFIX`.
+pub const BUTTONS: [(&str, Button); 19] = [
+    ("PadSouth", Button::South),
+    ("PadEast", Button::East),
+    ("PadNorth", Button::North),
+    ("PadWest", Button::West),
+    ("PadC", Button::C),
+    ("PadZ", Button::Z),
+    ("PadLeftTrigger", Button::LeftTrigger),
+    ("PadLeftTrigger2", Button::LeftTrigger2),
+    ("PadRightTrigger", Button::RightTrigger),
+    ("PadRightTrigger2", Button::RightTrigger2),
+    ("PadSelect", Button::Select),
+    ("PadStart", Button::Start),
+    ("PadMode", Button::Mode),
+    ("PadLeftThumb", Button::LeftThumb),
+    ("PadRightThumb", Button::RightThumb),
+    ("PadDPadUp", Button::DPadUp),
+    ("PadDPadDown", Button::DPadDown),
+    ("PadDPadLeft", Button::DPadLeft),
+    ("PadDPadRight", Button::DPadRight),
+];
+
+pub const AXES: [(&str, Axis); 8] = [
+    ("PadLeftStickX", Axis::LeftStickX),
+    ("PadLeftStickY", Axis::LeftStickY),
+    ("PadLeftZ", Axis::LeftZ),
+    ("PadRightStickX", Axis::RightStickX),
+    ("PadRightStickY", Axis::RightStickY),
+    ("PadRightZ", Axis::RightZ),
+    ("PadDPadX", Axis::DPadX),
+    ("PadDPadY", Axis::DPadY),
+];
+
+/// Axes are bound by their platform specific code as this followed by the number, like
+/// `PadAxis3`, for devices whose axes have no name. The code is printed the first time an
+/// unnamed axis moves.
+pub const RAW_AXIS_PREFIX: &str = "PadAxis";
+
+/// Every connected gamepad, joystick and HOTAS, read as if they were a single device.
+pub struct Gamepads {
+    /// Missing on platforms gilrs doesn't support, in which case nothing is ever pressed.
+    gilrs: Option<Gilrs>,
+    /// Buttons pressed on any device since the last poll.
+    pressed: Vec<Button>,
+    /// Codes of the unnamed axes already printed.
+    reported_axes: Vec<u32>,
+}
+
+impl Gamepads {
+    pub fn new() -> Self {
+        // The dead zone is the player's to pick in the input config, so gilrs shouldn't add its own
+        let gilrs = GilrsBuilder::new()
+            .with_default_filters(false)
+            .build()
+            .map_err(|e| println!("Gamepads unavailable: {}", e))
+            .ok();
+        Self {
+            gilrs,
+            pressed: Vec::new(),
+            reported_axes: Vec::new(),
+        }
+    }
+
+    /// Catches up on device events, needed once a frame for the state to change.
+    pub fn poll(&mut self) {
+        self.pressed.clear();
+        let Some(gilrs) = &mut self.gilrs else {
+            return;
+        };
+        while let Some(event) = gilrs.next_event() {
+            match event.event {
+                EventType::ButtonPressed(button, _) => self.pressed.push(button),
+                EventType::Connected => {
+                    println!("Gamepad connected: {}", gilrs.gamepad(event.id).name())
+                }
+                EventType::AxisChanged(Axis::Unknown, _, code) => {
+                    let code = code.into_u32();
+                    if !self.reported_axes.contains(&code) {
+                        self.reported_axes.push(code);
+                        println!(
+                            "Unnamed axis moved on {}, bind it as {}{}",
+                            gilrs.gamepad(event.id).name(),
+                            RAW_AXIS_PREFIX,
+                            code
+                        );
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Position of the axis from -1 to 1, taken from whichever device has it pushed furthest.
+    pub fn axis(&self, axis: Axis) -> f32 {
+        self.gilrs
+            .iter()
+            .flat_map(Gilrs::gamepads)
+            .fold(0., |value, (_, gamepad)| {
+                let other = gamepad.value(axis);
+                if other.abs() > value.abs() {
+                    other
+                } else {
+                    value
+                }
+            })
+    }
+
+    /// Position of an axis by its raw code, like `axis` for axes without a name.
+    pub fn raw_axis(&self, code: u32) -> f32 {
+        self.gilrs
+            .iter()
+            .flat_map(Gilrs::gamepads)
+            .fold(0., |value, (_, gamepad)| {
+                let other = gamepad
+                    .state()
+                    .axes()
+                    .find(|(other, _)| other.into_u32() == code)
+                    .map_or(0., |(_, data)| data.value());
+                if other.abs() > value.abs() {
+                    other
+                } else {
+                    value
+                }
+            })
+    }
+
+    pub fn button_down(&self, button: Button) -> bool {
+        self.gilrs
+            .iter()
+            .flat_map(Gilrs::gamepads)
+            .any(|(_, gamepad)| gamepad.is_pressed(button))
+    }
+
+    pub fn button_pressed(&self, button: Button) -> bool {
+        self.pressed.contains(&button)
+    }
+}
@@ -0,0 +1,783 @@
+use std::path::PathBuf;
+
+use anyhow::{anyhow, bail};
+use bevy_ecs::system::{NonSendMut, Res, ResMut, Resource};
+use macroquad::prelude::*;
+
+use crate::{
+    input::{
+        gamepad::{self, Gamepads},
+        mouse::MouseSettings,
+    },
+    utils::{file_utils::config_dir, input_utils::MouseInput},
+};
+
+/// Bindings and settings saved by the player in `config_dir`, overriding `DEFAULT_BINDINGS` line by
+/// line.
+pub const INPUT_CONFIG: &str = "input.cfg";
+
+/// One `action = binding, binding` line per action. Keys use macroquad's `KeyCode` names, mouse
+/// buttons are `MouseLeft`, `MouseRight` and `MouseMiddle`, and mouse axes are `MouseX`, `MouseY`
+/// and `MouseWheel`. Gamepad buttons and axes are gilrs' names prefixed with `Pad`, like
+/// `PadSouth` or `PadLeftStickX`. Any axis can be inverted with a leading `-`.
+///
+/// Analog axes are shaped by `axis_dead_zone`, the fraction of their travel around the centre
+/// that is ignored, and `axis_curve`, the exponent applied past it for finer control near the
+/// centre. The `mouse_` settings are described on `MouseSettings`.
+const DEFAULT_BINDINGS: &str = "
+axis_dead_zone = 0.15
+axis_curve = 2
+mouse_sensitivity = 1
+mouse_invert_y = false
+mouse_smoothing = 0
+mouse_acceleration = 0
+thrust_forward = W, Up
+thrust_backward = S, Down
+throttle = PadRightStickY
+strafe_left = A, Left
+strafe_right = D, Right
+strafe_up = Space, PadRightTrigger
+strafe_down = LeftControl, PadLeftTrigger
+roll_left = Q
+roll_right = E
+roll = PadRightStickX
+pitch = -MouseY, -PadLeftStickY
+yaw = -MouseX, -PadLeftStickX
+afterburner = LeftShift, PadLeftTrigger2
+fire = MouseLeft, F, PadRightTrigger2
+weapon_1 = Key1
+weapon_2 = Key2
+weapon_3 = Key3
+weapon_4 = Key4
+pips_engines = F1
+pips_shields = F2
+pips_weapons = F3
+balance_pips = F4
+flight_assist = Z, PadWest
+target = T, PadNorth
+camera_mode = C, PadSelect
+zoom = MouseWheel
+depth_mode = L
+toggle_grab = Tab
+edit_bindings = F10
+quit = Escape
+";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Action {
+    ThrustForward,
+    ThrustBackward,
+    Throttle,
+    StrafeLeft,
+    StrafeRight,
+    StrafeUp,
+    StrafeDown,
+    RollLeft,
+    RollRight,
+    Roll,
+    Pitch,
+    Yaw,
+    Afterburner,
+    Fire,
+    Weapon1,
+    Weapon2,
+    Weapon3,
+    Weapon4,
+    PipsEngines,
+    PipsShields,
+    PipsWeapons,
+    BalancePips,
+    FlightAssist,
+    Target,
+    CameraMode,
+    Zoom,
+    DepthMode,
+    ToggleGrab,
+    EditBindings,
+    Quit,
+}
+
+impl Action {
+    pub const ALL: [Action; 30] = [
+        Action::ThrustForward,
+        Action::ThrustBackward,
+        Action::Throttle,
+        Action::StrafeLeft,
+        Action::StrafeRight,
+        Action::StrafeUp,
+        Action::StrafeDown,
+        Action::RollLeft,
+        Action::RollRight,
+        Action::Roll,
+        Action::Pitch,
+        Action::Yaw,
+        Action::Afterburner,
+        Action::Fire,
+        Action::Weapon1,
+        Action::Weapon2,
+        Action::Weapon3,
+        Action::Weapon4,
+        Action::PipsEngines,
+        Action::PipsShields,
+        Action::PipsWeapons,
+        Action::BalancePips,
+        Action::FlightAssist,
+        Action::Target,
+        Action::CameraMode,
+        Action::Zoom,
+        Action::DepthMode,
+        Action::ToggleGrab,
+        Action::EditBindings,
+        Action::Quit,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::ThrustForward => "thrust_forward",
+            Action::ThrustBackward => "thrust_backward",
+            Action::Throttle => "throttle",
+            Action::StrafeLeft => "strafe_left",
+            Action::StrafeRight => "strafe_right",
+            Action::StrafeUp => "strafe_up",
+            Action::StrafeDown => "strafe_down",
+            Action::RollLeft => "roll_left",
+            Action::RollRight => "roll_right",
+            Action::Roll => "roll",
+            Action::Pitch => "pitch",
+            Action::Yaw => "yaw",
+            Action::Afterburner => "afterburner",
+            Action::Fire => "fire",
+            Action::Weapon1 => "weapon_1",
+            Action::Weapon2 => "weapon_2",
+            Action::Weapon3 => "weapon_3",
+            Action::Weapon4 => "weapon_4",
+            Action::PipsEngines => "pips_engines",
+            Action::PipsShields => "pips_shields",
+            Action::PipsWeapons => "pips_weapons",
+            Action::BalancePips => "balance_pips",
+            Action::FlightAssist => "flight_assist",
+            Action::Target => "target",
+            Action::CameraMode => "camera_mode",
+            Action::Zoom => "zoom",
+            Action::DepthMode => "depth_mode",
+            Action::ToggleGrab => "toggle_grab",
+            Action::EditBindings => "edit_bindings",
+            Action::Quit => "quit",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        Action::ALL.into_iter().find(|action| action.name() == name)
+    }
+
+    /// Whether the action is driven by mouse movement or analog axes rather than pressed like a
+    /// button.
+    pub fn is_axis(self) -> bool {
+        matches!(
+            self,
+            Action::Throttle | Action::Roll | Action::Pitch | Action::Yaw | Action::Zoom
+        )
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MouseAxis {
+    X,
+    Y,
+    Wheel,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    /// Pixels the mouse moved this frame, or how far the wheel was scrolled.
+    MouseAxis {
+        axis: MouseAxis,
+        inverted: bool,
+    },
+    PadButton(gilrs::Button),
+    /// Position of a stick, trigger or throttle from -1 to 1.
+    PadAxis {
+        axis: gilrs::Axis,
+        inverted: bool,
+    },
+    /// Like `PadAxis`, for axes only known by their raw code.
+    PadRawAxis {
+        code: u32,
+        inverted: bool,
+    },
+}
+
+/// Names of the keys that can be bound, as written in the config file. Every `KeyCode` but
+/// `Unknown` is listed, so any key the editor captures can be saved and loaded back.
+const KEYS: [(&str, KeyCode); 121] = [
+    ("A", KeyCode::A),
+    ("B", KeyCode::B),
+    ("C", KeyCode::C),
+    ("D", KeyCode::D),
+    ("E", KeyCode::E),
+    ("F", KeyCode::F),
+    ("G", KeyCode::G),
+    ("H", KeyCode::H),
+    ("I", KeyCode::I),
+    ("J", KeyCode::J),
+    ("K", KeyCode::K),
+    ("L", KeyCode::L),
+    ("M", KeyCode::M),
+    ("N", KeyCode::N),
+    ("O", KeyCode::O),
+    ("P", KeyCode::P),
+    ("Q", KeyCode::Q),
+    ("R", KeyCode::R),
+    ("S", KeyCode::S),
+    ("T", KeyCode::T),
+    ("U", KeyCode::U),
+    ("V", KeyCode::V),
+    ("W", KeyCode::W),
+    ("X", KeyCode::X),
+    ("Y", KeyCode::Y),
+    ("Z", KeyCode::Z),
+    ("Key0", KeyCode::Key0),
+    ("Key1", KeyCode::Key1),
+    ("Key2", KeyCode::Key2),
+    ("Key3", KeyCode::Key3),
+    ("Key4", KeyCode::Key4),
+    ("Key5", KeyCode::Key5),
+    ("Key6", KeyCode::Key6),
+    ("Key7", KeyCode::Key7),
+    ("Key8", KeyCode::Key8),
+    ("Key9", KeyCode::Key9),
+    ("F1", KeyCode::F1),
+    ("F2", KeyCode::F2),
+    ("F3", KeyCode::F3),
+    ("F4", KeyCode::F4),
+    ("F5", KeyCode::F5),
+    ("F6", KeyCode::F6),
+    ("F7", KeyCode::F7),
+    ("F8", KeyCode::F8),
+    ("F9", KeyCode::F9),
+    ("F10", KeyCode::F10),
+    ("F11", KeyCode::F11),
+    ("F12", KeyCode::F12),
+    ("Up", KeyCode::Up),
+    ("Down", KeyCode::Down),
+    ("Left", KeyCode::Left),
+    ("Right", KeyCode::Right),
+    ("Space", KeyCode::Space),
+    ("Tab", KeyCode::Tab),
+    ("Escape", KeyCode::Escape),
+    ("Enter", KeyCode::Enter),
+    ("Backspace", KeyCode::Backspace),
+    ("Insert", KeyCode::Insert),
+    ("Delete", KeyCode::Delete),
+    ("Home", KeyCode::Home),
+    ("End", KeyCode::End),
+    ("PageUp", KeyCode::PageUp),
+    ("PageDown", KeyCode::PageDown),
+    ("LeftShift", KeyCode::LeftShift),
+    ("RightShift", KeyCode::RightShift),
+    ("LeftControl", KeyCode::LeftControl),
+    ("RightControl", KeyCode::RightControl),
+    ("LeftAlt", KeyCode::LeftAlt),
+    ("Apostrophe", KeyCode::Apostrophe),
+    ("Comma", KeyCode::Comma),
+    ("Minus", KeyCode::Minus),
+    ("Period", KeyCode::Period),
+    ("Slash", KeyCode::Slash),
+    ("Semicolon", KeyCode::Semicolon),
+    ("Equal", KeyCode::Equal),
+    ("LeftBracket", KeyCode::LeftBracket),
+    ("Backslash", KeyCode::Backslash),
+    ("RightBracket", KeyCode::RightBracket),
+    ("GraveAccent", KeyCode::GraveAccent),
+    ("World1", KeyCode::World1),
+    ("World2", KeyCode::World2),
+    ("CapsLock", KeyCode::CapsLock),
+    ("ScrollLock", KeyCode::ScrollLock),
+    ("NumLock", KeyCode::NumLock),
+    ("PrintScreen", KeyCode::PrintScreen),
+    ("Pause", KeyCode::Pause),
+    ("F13", KeyCode::F13),
+    ("F14", KeyCode::F14),
+    ("F15", KeyCode::F15),
+    ("F16", KeyCode::F16),
+    ("F17", KeyCode::F17),
+    ("F18", KeyCode::F18),
+    ("F19", KeyCode::F19),
+    ("F20", KeyCode::F20),
+    ("F21", KeyCode::F21),
+    ("F22", KeyCode::F22),
+    ("F23", KeyCode::F23),
+    ("F24", KeyCode::F24),
+    ("F25", KeyCode::F25),
+    ("Kp0", KeyCode::Kp0),
+    ("Kp1", KeyCode::Kp1),
+    ("Kp2", KeyCode::Kp2),
+    ("Kp3", KeyCode::Kp3),
+    ("Kp4", KeyCode::Kp4),
+    ("Kp5", KeyCode::Kp5),
+    ("Kp6", KeyCode::Kp6),
+    ("Kp7", KeyCode::Kp7),
+    ("Kp8", KeyCode::Kp8),
+    ("Kp9", KeyCode::Kp9),
+    ("KpDecimal", KeyCode::KpDecimal),
+    ("KpDivide", KeyCode::KpDivide),
+    ("KpMultiply", KeyCode::KpMultiply),
+    ("KpSubtract", KeyCode::KpSubtract),
+    ("KpAdd", KeyCode::KpAdd),
+    ("KpEnter", KeyCode::KpEnter),
+    ("KpEqual", KeyCode::KpEqual),
+    ("LeftSuper", KeyCode::LeftSuper),
+    ("RightAlt", KeyCode::RightAlt),
+    ("RightSuper", KeyCode::RightSuper),
+    ("Menu", KeyCode::Menu),
+    ("Back", KeyCode::Back),
+];
+
+const MOUSE_BUTTONS: [(&str, MouseButton); 3] = [
+    ("MouseLeft", MouseButton::Left),
+    ("MouseRight", MouseButton::Right),
+    ("MouseMiddle", MouseButton::Middle),
+];
+
+const MOUSE_AXES: [(&str, MouseAxis); 3] = [
+    ("MouseX", MouseAxis::X),
+    ("MouseY", MouseAxis::Y),
+    ("MouseWheel", MouseAxis::Wheel),
+];
+
+/// Looks a name up in one of the name tables.
+fn find_name<T: Copy>(table: &[(&str, T)], name: &str) -> Option<T> {
+    table
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, value)| *value)
+}
+
+fn name_of<T: PartialEq>(table: &[(&'static str, T)], value: &T) -> &'static str {
+    table
+        .iter()
+        .find(|(_, v)| v == value)
+        .map_or("?", |(name, _)| name)
+}
+
+impl Binding {
+    /// The key as a binding, or `None` for keys that have no name in the config file.
+    pub fn key(key: KeyCode) -> Option<Self> {
+        KEYS.iter()
+            .any(|(_, k)| *k == key)
+            .then_some(Binding::Key(key))
+    }
+
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let (inverted, name) = match text.strip_prefix('-') {
+            Some(name) => (true, name),
+            None => (false, text),
+        };
+
+        if let Some(axis) = find_name(&MOUSE_AXES, name) {
+            return Ok(Binding::MouseAxis { axis, inverted });
+        }
+        if let Some(axis) = find_name(&gamepad::AXES, name) {
+            return Ok(Binding::PadAxis { axis, inverted });
+        }
+        if let Some(code) = name
+            .strip_prefix(gamepad::RAW_AXIS_PREFIX)
+            .and_then(|code| code.parse().ok())
+        {
+            return Ok(Binding::PadRawAxis { code, inverted });
+        }
+        if inverted {
+            bail!("Only axes can be inverted, not {}", name);
+        }
+        find_name(&KEYS, name)
+            .map(Binding::Key)
+            .or_else(|| find_name(&MOUSE_BUTTONS, name).map(Binding::Mouse))
+            .or_else(|| find_name(&gamepad::BUTTONS, name).map(Binding::PadButton))
+            .ok_or_else(|| anyhow!("Unknown key or button {}", name))
+    }
+
+    /// Name as written in the config file.
+    pub fn name(&self) -> String {
+        let sign = |inverted: bool| if inverted { "-" } else { "" };
+        match self {
+            Binding::Key(key) => name_of(&KEYS, key).to_string(),
+            Binding::Mouse(button) => name_of(&MOUSE_BUTTONS, button).to_string(),
+            Binding::MouseAxis { axis, inverted } => {
+                format!("{}{}", sign(*inverted), name_of(&MOUSE_AXES, axis))
+            }
+            Binding::PadButton(button) => name_of(&gamepad::BUTTONS, button).to_string(),
+            Binding::PadAxis { axis, inverted } => {
+                format!("{}{}", sign(*inverted), name_of(&gamepad::AXES, axis))
+            }
+            Binding::PadRawAxis { code, inverted } => {
+                format!("{}{}{}", sign(*inverted), gamepad::RAW_AXIS_PREFIX, code)
+            }
+        }
+    }
+
+    /// How strongly a button or analog axis is held, zero for the mouse axes.
+    fn value(&self, gamepads: &Gamepads, input_map: &InputMap) -> f32 {
+        match *self {
+            Binding::Key(key) => is_key_down(key) as i32 as f32,
+            Binding::Mouse(button) => is_mouse_button_down(button) as i32 as f32,
+            Binding::PadButton(button) => gamepads.button_down(button) as i32 as f32,
+            Binding::PadAxis { axis, inverted } => {
+                let value = input_map.shape_axis(gamepads.axis(axis));
+                if inverted {
+                    -value
+                } else {
+                    value
+                }
+            }
+            Binding::PadRawAxis { code, inverted } => {
+                let value = input_map.shape_axis(gamepads.raw_axis(code));
+                if inverted {
+                    -value
+                } else {
+                    value
+                }
+            }
+            Binding::MouseAxis { .. } => 0.,
+        }
+    }
+
+    /// How far a mouse axis moved this frame, zero for everything else.
+    fn motion(&self, mouse_input: &MouseInput) -> f32 {
+        let Binding::MouseAxis { axis, inverted } = *self else {
+            return 0.;
+        };
+        let motion = match axis {
+            MouseAxis::X => mouse_input.mouse_delta.x,
+            MouseAxis::Y => mouse_input.mouse_delta.y,
+            MouseAxis::Wheel => mouse_wheel().1,
+        };
+        if inverted {
+            -motion
+        } else {
+            motion
+        }
+    }
+
+    fn pressed(&self, gamepads: &Gamepads) -> bool {
+        match *self {
+            Binding::Key(key) => is_key_pressed(key),
+            Binding::Mouse(button) => is_mouse_button_pressed(button),
+            Binding::PadButton(button) => gamepads.button_pressed(button),
+            Binding::MouseAxis { .. } | Binding::PadAxis { .. } | Binding::PadRawAxis { .. } => {
+                false
+            }
+        }
+    }
+}
+
+/// Which keys, mouse buttons and axes trigger each action.
+#[derive(Resource)]
+pub struct InputMap {
+    bindings: Vec<Vec<Binding>>,
+    /// Fraction of an analog axis' travel around the centre that is ignored.
+    pub dead_zone: f32,
+    /// Exponent applied to analog axes past the dead zone, above 1 for finer control near the
+    /// centre.
+    pub curve: f32,
+    pub mouse: MouseSettings,
+    /// Set while the bindings editor is open, so playing doesn't react to keys being rebound.
+    pub(crate) editing: bool,
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        let mut map = Self {
+            bindings: vec![Vec::new(); Action::ALL.len()],
+            dead_zone: 0.,
+            curve: 1.,
+            mouse: MouseSettings::default(),
+            editing: false,
+        };
+        map.apply(DEFAULT_BINDINGS)
+            .expect("Default bindings should be valid");
+        map
+    }
+}
+
+impl InputMap {
+    /// Default bindings with the player's saved ones on top. A broken config is reported and
+    /// ignored line by line rather than failing the whole load.
+    pub fn load() -> Self {
+        let mut map = Self::default();
+        if let Ok(config) = std::fs::read_to_string(Self::config_path()) {
+            if let Err(e) = map.apply(&config) {
+                println!("Failed to load {}: {}", INPUT_CONFIG, e);
+            }
+        }
+        map
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        std::fs::create_dir_all(config_dir())?;
+        std::fs::write(Self::config_path(), self.config())?;
+        Ok(())
+    }
+
+    /// The settings and bindings in config file form, the counterpart of `apply`.
+    fn config(&self) -> String {
+        let mut config = format!(
+            "axis_dead_zone = {}\naxis_curve = {}\n",
+            self.dead_zone, self.curve
+        ) + &self.mouse.save();
+        for action in Action::ALL {
+            let bindings = self
+                .bindings(action)
+                .iter()
+                .map(Binding::name)
+                .collect::<Vec<_>>();
+            config += &format!("{} = {}\n", action.name(), bindings.join(", "));
+        }
+        config
+    }
+
+    fn config_path() -> PathBuf {
+        config_dir().join(INPUT_CONFIG)
+    }
+
+    /// Replaces the bindings of every action listed in the config, keeping the last error.
+    fn apply(&mut self, config: &str) -> anyhow::Result<()> {
+        let mut result = Ok(());
+        let lines = config
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'));
+        for line in lines {
+            if let Err(e) = self.apply_line(line) {
+                result = Err(e);
+            }
+        }
+        result
+    }
+
+    fn apply_line(&mut self, line: &str) -> anyhow::Result<()> {
+        let (name, bindings) = line
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Expected action = bindings in '{}'", line))?;
+        match name.trim() {
+            "axis_dead_zone" => {
+                self.dead_zone = bindings.trim().parse::<f32>()?.clamp(0., 0.95);
+                return Ok(());
+            }
+            "axis_curve" => {
+                self.curve = bindings.trim().parse::<f32>()?.max(0.1);
+                return Ok(());
+            }
+            name => {
+                if self.mouse.apply(name, bindings.trim())? {
+                    return Ok(());
+                }
+            }
+        }
+        let action = Action::from_name(name.trim())
+            .ok_or_else(|| anyhow!("Unknown action {}", name.trim()))?;
+        let bindings = bindings
+            .split(',')
+            .map(str::trim)
+            .filter(|binding| !binding.is_empty())
+            .map(Binding::parse)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        self.rebind(action, bindings);
+        Ok(())
+    }
+
+    pub fn bindings(&self, action: Action) -> &[Binding] {
+        &self.bindings[action as usize]
+    }
+
+    pub fn rebind(&mut self, action: Action, bindings: Vec<Binding>) {
+        self.bindings[action as usize] = bindings;
+    }
+
+    /// Applies the dead zone and curve to the raw position of an analog axis.
+    fn shape_axis(&self, value: f32) -> f32 {
+        let travel = ((value.abs() - self.dead_zone) / (1. - self.dead_zone)).max(0.);
+        travel.powf(self.curve).copysign(value)
+    }
+
+    /// Bindings of the action for showing on the HUD, like `W/Up`.
+    pub fn label(&self, action: Action) -> String {
+        let names = self
+            .bindings(action)
+            .iter()
+            .map(Binding::name)
+            .collect::<Vec<_>>();
+        if names.is_empty() {
+            "unbound".to_string()
+        } else {
+            names.join("/")
+        }
+    }
+}
+
+/// State of every action this frame, read by gameplay instead of the raw devices.
+#[derive(Resource, Clone)]
+pub struct Actions {
+    values: [f32; Action::ALL.len()],
+    motions: [f32; Action::ALL.len()],
+    pressed: [bool; Action::ALL.len()],
+}
+
+impl Default for Actions {
+    fn default() -> Self {
+        Self {
+            values: [0.; Action::ALL.len()],
+            motions: [0.; Action::ALL.len()],
+            pressed: [false; Action::ALL.len()],
+        }
+    }
+}
+
+impl Actions {
+    /// How strongly the action is held, 0 or 1 for buttons and -1 to 1 for analog axes.
+    pub fn value(&self, action: Action) -> f32 {
+        self.values[action as usize]
+    }
+
+    /// Mouse movement driving the action this frame. Unlike `value` it is a distance rather than
+    /// a position, so it is up to the reader whether it turns into a rate or an angle.
+    pub fn motion(&self, action: Action) -> f32 {
+        self.motions[action as usize]
+    }
+
+    pub fn down(&self, action: Action) -> bool {
+        self.value(action) != 0.
+    }
+
+    /// Whether the action was triggered this frame.
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed[action as usize]
+    }
+
+    /// From -1 to 1 depending on how hard each of two opposing actions are held.
+    pub fn axis(&self, negative: Action, positive: Action) -> f32 {
+        self.value(positive) - self.value(negative)
+    }
+
+    /// Every action as a line of text that `decode` reads back exactly, values and motions
+    /// followed by a 0 or 1 for each press.
+    pub fn encode(&self) -> String {
+        let numbers = self.values.iter().chain(&self.motions).map(f32::to_string);
+        let pressed = self
+            .pressed
+            .iter()
+            .map(|&pressed| (pressed as u8).to_string());
+        numbers.chain(pressed).collect::<Vec<_>>().join(" ")
+    }
+
+    pub fn decode(line: &str) -> anyhow::Result<Self> {
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+        let count = Action::ALL.len();
+        if fields.len() != count * 3 {
+            bail!(
+                "Expected {} fields for the actions, got {}",
+                count * 3,
+                fields.len()
+            );
+        }
+
+        let mut actions = Self::default();
+        for i in 0..count {
+            actions.values[i] = fields[i].parse()?;
+            actions.motions[i] = fields[count + i].parse()?;
+            actions.pressed[i] = fields[count * 2 + i] == "1";
+        }
+        Ok(actions)
+    }
+}
+
+/// Samples every binding once a frame, before anything reads the actions.
+pub fn read_actions(
+    input_map: Res<InputMap>,
+    mouse_input: Res<MouseInput>,
+    mut gamepads: NonSendMut<Gamepads>,
+    mut actions: ResMut<Actions>,
+) {
+    gamepads.poll();
+    for action in Action::ALL {
+        let bindings = input_map.bindings(action);
+        let value = bindings
+            .iter()
+            .map(|binding| binding.value(&gamepads, &input_map))
+            .sum::<f32>()
+            .clamp(-1., 1.);
+        let motion = bindings
+            .iter()
+            .map(|binding| binding.motion(&mouse_input))
+            .sum::<f32>();
+        let pressed = bindings.iter().any(|binding| binding.pressed(&gamepads));
+
+        // Only the editor's own toggle gets through while bindings are being edited
+        let blocked = input_map.editing && action != Action::EditBindings;
+        actions.values[action as usize] = if blocked { 0. } else { value };
+        actions.motions[action as usize] = if blocked { 0. } else { motion };
+        actions.pressed[action as usize] = pressed && !blocked;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn actions_round_trip_exactly() {
+        let mut actions = Actions::default();
+        for (i, action) in Action::ALL.into_iter().enumerate() {
+            actions.values[action as usize] = (i as f32 * 0.1).sin();
+            actions.motions[action as usize] = i as f32 * -3.7 + f32::EPSILON;
+            actions.pressed[action as usize] = i % 3 == 0;
+        }
+        actions.motions[0] = f32::MIN_POSITIVE;
+
+        let decoded = Actions::decode(&actions.encode()).unwrap();
+        for action in Action::ALL {
+            assert_eq!(
+                decoded.value(action).to_bits(),
+                actions.value(action).to_bits()
+            );
+            assert_eq!(
+                decoded.motion(action).to_bits(),
+                actions.motion(action).to_bits()
+            );
+            assert_eq!(decoded.pressed(action), actions.pressed(action));
+        }
+    }
+
+    #[test]
+    fn actions_reject_missing_fields() {
+        let encoded = Actions::default().encode();
+        let (truncated, _) = encoded.rsplit_once(' ').unwrap();
+        assert!(Actions::decode(truncated).is_err());
+    }
+
+    #[test]
+    fn rebound_keys_survive_save_and_load() {
+        let mut map = InputMap::default();
+        map.rebind(Action::Fire, vec![Binding::Key(KeyCode::KpAdd)]);
+        map.rebind(
+            Action::Throttle,
+            vec![Binding::PadRawAxis {
+                code: 65538,
+                inverted: true,
+            }],
+        );
+        map.rebind(
+            Action::Afterburner,
+            KEYS.iter().map(|(_, key)| Binding::Key(*key)).collect(),
+        );
+        map.dead_zone = 0.3;
+
+        let mut loaded = InputMap::default();
+        loaded.apply(&map.config()).unwrap();
+        for action in Action::ALL {
+            assert_eq!(loaded.bindings(action), map.bindings(action));
+        }
+        assert_eq!(loaded.dead_zone, map.dead_zone);
+    }
+}
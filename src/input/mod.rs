@@ -0,0 +1,5 @@
+pub mod gamepad;
+pub mod input_map;
+pub mod mouse;
+pub mod rebind;
+pub mod replay;
@@ -0,0 +1,139 @@
+use anyhow::anyhow;
+use bevy_ecs::system::{Res, ResMut, Resource};
+use macroquad::{
+    input::utils::{register_input_subscriber, repeat_all_miniquad_input},
+    miniquad::EventHandler,
+    prelude::*,
+};
+
+use crate::{input::input_map::InputMap, time::time::Time, utils::input_utils::MouseInput};
+
+/// Mouse speed, in pixels per second, that adds `acceleration` to the sensitivity.
+const ACCELERATION_SPEED: f32 = 1000.;
+
+/// How the player likes the mouse to feel, saved alongside the bindings.
+#[derive(Clone, Copy)]
+pub struct MouseSettings {
+    /// Multiplier on every pixel of movement.
+    pub sensitivity: f32,
+    pub invert_y: bool,
+    /// Seconds the movement is averaged over, 0 for none.
+    pub smoothing: f32,
+    /// Extra sensitivity for every `ACCELERATION_SPEED` of mouse speed, 0 for none.
+    pub acceleration: f32,
+}
+
+impl Default for MouseSettings {
+    fn default() -> Self {
+        Self {
+            sensitivity: 1.,
+            invert_y: false,
+            smoothing: 0.,
+            acceleration: 0.,
+        }
+    }
+}
+
+impl MouseSettings {
+    /// Config lines for each setting, the counterpart of `apply`.
+    pub fn save(&self) -> String {
+        format!(
+            "mouse_sensitivity = {}\nmouse_invert_y = {}\nmouse_smoothing = {}\nmouse_acceleration = {}\n",
+            self.sensitivity, self.invert_y, self.smoothing, self.acceleration
+        )
+    }
+
+    /// Sets the named setting, returning whether the name was one of them.
+    pub fn apply(&mut self, name: &str, value: &str) -> anyhow::Result<bool> {
+        let number = || {
+            value
+                .parse::<f32>()
+                .map_err(|e| anyhow!("Bad value for {}: {}", name, e))
+        };
+        match name {
+            "mouse_sensitivity" => self.sensitivity = number()?.max(0.),
+            "mouse_invert_y" => self.invert_y = value.parse()?,
+            "mouse_smoothing" => self.smoothing = number()?.max(0.),
+            "mouse_acceleration" => self.acceleration = number()?.max(0.),
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+}
+
+/// Mouse movement summed from every motion event since the last frame. While the cursor is
+/// grabbed macroquad feeds these from the raw device motion, so unlike comparing cursor positions
+/// it keeps going when the cursor would have hit the edge of the window.
+#[derive(Resource)]
+pub struct RawMouse {
+    subscriber: usize,
+    last_position: Option<Vec2>,
+    motion: Vec2,
+    /// Smoothed mouse speed in pixels per second.
+    velocity: Vec2,
+}
+
+impl RawMouse {
+    pub fn new() -> Self {
+        Self {
+            subscriber: register_input_subscriber(),
+            last_position: None,
+            motion: Vec2::ZERO,
+            velocity: Vec2::ZERO,
+        }
+    }
+
+    /// Forgets where the cursor was, for when it jumps without the mouse moving, like when the
+    /// grab is toggled.
+    pub fn reset(&mut self) {
+        self.last_position = None;
+        self.velocity = Vec2::ZERO;
+    }
+
+    fn take_motion(&mut self) -> Vec2 {
+        let subscriber = self.subscriber;
+        repeat_all_miniquad_input(self, subscriber);
+        std::mem::take(&mut self.motion)
+    }
+}
+
+impl EventHandler for RawMouse {
+    fn update(&mut self) {}
+
+    fn draw(&mut self) {}
+
+    fn mouse_motion_event(&mut self, x: f32, y: f32) {
+        let position = vec2(x, y);
+        if let Some(last_position) = self.last_position {
+            self.motion += position - last_position;
+        }
+        self.last_position = Some(position);
+    }
+}
+
+/// Turns the frame's raw mouse motion into `MouseInput` with the player's settings applied.
+pub fn read_mouse(
+    mut raw_mouse: ResMut<RawMouse>,
+    input_map: Res<InputMap>,
+    mut mouse_input: ResMut<MouseInput>,
+    time: Res<Time>,
+) {
+    let settings = input_map.mouse;
+    let dt = time.delta();
+    let mut motion = raw_mouse.take_motion();
+    if dt > 0. {
+        motion *= 1. + settings.acceleration * motion.length() / dt / ACCELERATION_SPEED;
+    }
+    motion *= settings.sensitivity;
+    if settings.invert_y {
+        motion.y = -motion.y;
+    }
+
+    // Smooth the speed rather than the distance so it settles the same at any frame rate
+    if settings.smoothing > 0. && dt > 0. {
+        let blend = 1. - (-dt / settings.smoothing).exp();
+        raw_mouse.velocity = raw_mouse.velocity.lerp(motion / dt, blend);
+        motion = raw_mouse.velocity * dt;
+    }
+    mouse_input.mouse_delta = motion;
+}
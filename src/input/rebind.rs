@@ -0,0 +1,124 @@
+use bevy_ecs::system::{NonSend, Res, ResMut, Resource};
+use macroquad::prelude::*;
+
+use crate::input::{
+    gamepad::{self, Gamepads},
+    input_map::{Action, Actions, Binding, InputMap},
+};
+
+/// In-game editor for the button bindings, opened with the `edit_bindings` action. Axes can only
+/// be rebound in the config file.
+#[derive(Resource, Default)]
+pub struct BindingsEditor {
+    selected: usize,
+    /// Waiting for the key, mouse or gamepad button to bind the selected action to.
+    capturing: bool,
+}
+
+fn editable() -> Vec<Action> {
+    Action::ALL
+        .into_iter()
+        .filter(|action| !action.is_axis())
+        .collect()
+}
+
+/// Navigates the editor with the arrow keys, starting a rebind with Enter and resetting an action
+/// to its defaults with Backspace. The editor's own keys are fixed so it can't be locked out.
+pub fn edit_bindings(
+    mut input_map: ResMut<InputMap>,
+    mut editor: ResMut<BindingsEditor>,
+    actions: Res<Actions>,
+    gamepads: NonSend<Gamepads>,
+) {
+    if !editor.capturing && actions.pressed(Action::EditBindings) {
+        input_map.editing = !input_map.editing;
+        return;
+    }
+    if !input_map.editing {
+        return;
+    }
+
+    let editable = editable();
+    let action = editable[editor.selected];
+    if editor.capturing {
+        if is_key_pressed(KeyCode::Escape) {
+            editor.capturing = false;
+            return;
+        }
+        let binding = get_last_key_pressed().and_then(Binding::key).or_else(|| {
+            [MouseButton::Left, MouseButton::Right, MouseButton::Middle]
+                .into_iter()
+                .find(|button| is_mouse_button_pressed(*button))
+                .map(Binding::Mouse)
+                .or_else(|| {
+                    gamepad::BUTTONS
+                        .iter()
+                        .find(|(_, button)| gamepads.button_pressed(*button))
+                        .map(|(_, button)| Binding::PadButton(*button))
+                })
+        });
+        if let Some(binding) = binding {
+            input_map.rebind(action, vec![binding]);
+            editor.capturing = false;
+            if let Err(e) = input_map.save() {
+                println!("Failed to save bindings: {}", e);
+            }
+        }
+        return;
+    }
+
+    if is_key_pressed(KeyCode::Up) {
+        editor.selected = (editor.selected + editable.len() - 1) % editable.len();
+    }
+    if is_key_pressed(KeyCode::Down) {
+        editor.selected = (editor.selected + 1) % editable.len();
+    }
+    if is_key_pressed(KeyCode::Enter) {
+        editor.capturing = true;
+    }
+    if is_key_pressed(KeyCode::Backspace) {
+        let defaults = InputMap::default().bindings(action).to_vec();
+        input_map.rebind(action, defaults);
+        if let Err(e) = input_map.save() {
+            println!("Failed to save bindings: {}", e);
+        }
+    }
+}
+
+pub fn draw_bindings_editor(input_map: Res<InputMap>, editor: Res<BindingsEditor>) {
+    if !input_map.editing {
+        return;
+    }
+
+    let x = screen_width() / 2. - 250.;
+    let editable = editable();
+    draw_rectangle(
+        x - 20.,
+        40.,
+        540.,
+        80. + editable.len() as f32 * 22.,
+        Color::new(0., 0., 0., 0.8),
+    );
+    draw_text(
+        "Bindings - <Up>/<Down> select, <Enter> rebind, <Backspace> reset",
+        x,
+        70.,
+        20.0,
+        WHITE,
+    );
+    for (i, action) in editable.into_iter().enumerate() {
+        let selected = i == editor.selected;
+        let bindings = if selected && editor.capturing {
+            "press a key or button, <Escape> to cancel".to_string()
+        } else {
+            input_map.label(action)
+        };
+        draw_text(
+            format!("{:<16} {}", action.name(), bindings).as_str(),
+            x,
+            100. + i as f32 * 22.,
+            20.0,
+            if selected { YELLOW } else { WHITE },
+        );
+    }
+}
@@ -0,0 +1,251 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::{anyhow, bail};
+use bevy_ecs::system::{Res, ResMut, Resource};
+use macroquad::prelude::*;
+
+use crate::{
+    input::input_map::{Action, Actions},
+    utils::input_utils::MouseInput,
+};
+
+/// First line of a recording, followed by the random seed and then a line per frame.
+const REPLAY_HEADER: &str = "space_retro replay 1";
+
+/// Everything the simulation took from the player in one frame.
+pub struct Frame {
+    frame_time: f32,
+    mouse_delta: Vec2,
+    actions: Actions,
+}
+
+impl Frame {
+    fn encode(&self) -> String {
+        format!(
+            "{} {} {} {}",
+            self.frame_time,
+            self.mouse_delta.x,
+            self.mouse_delta.y,
+            self.actions.encode()
+        )
+    }
+
+    fn decode(line: &str) -> anyhow::Result<Self> {
+        let mut fields = line.splitn(4, ' ');
+        let mut number = || -> anyhow::Result<f32> {
+            Ok(fields
+                .next()
+                .ok_or_else(|| anyhow!("Frame ended early"))?
+                .parse()?)
+        };
+        let frame_time = number()?;
+        let mouse_delta = vec2(number()?, number()?);
+        let actions = Actions::decode(fields.next().unwrap_or_default())?;
+        Ok(Self {
+            frame_time,
+            mouse_delta,
+            actions,
+        })
+    }
+}
+
+/// Records the input of every frame to a file, or plays a recording back in place of the devices.
+/// Recording starts with the game, so playing it back from a fresh start reproduces the run
+/// exactly: the simulation only ever sees the actions, the frame times and the random seed.
+///
+/// Started with `--record <file>` or `--replay <file>`. Pressing any key during playback hands
+/// control back to the player.
+#[derive(Resource)]
+pub enum InputReplay {
+    Off,
+    Recording {
+        file: BufWriter<File>,
+        frame_time: f32,
+    },
+    Playing {
+        frames: Vec<Frame>,
+        /// Frames started so far, the last of them being the one playing.
+        played: usize,
+    },
+}
+
+impl InputReplay {
+    /// Reads the command line, reporting a bad recording and playing live instead.
+    pub fn from_args() -> Self {
+        let args = std::env::args().collect::<Vec<_>>();
+        let path_after = |flag: &str| {
+            args.iter()
+                .position(|arg| arg == flag)
+                .and_then(|i| args.get(i + 1))
+        };
+
+        let replay = if let Some(path) = path_after("--replay") {
+            Self::play(path)
+        } else if let Some(path) = path_after("--record") {
+            Self::record(path)
+        } else {
+            return Self::Off;
+        };
+        replay.unwrap_or_else(|e| {
+            println!("Failed to start the replay: {}", e);
+            Self::Off
+        })
+    }
+
+    fn record(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let seed = macroquad::miniquad::date::now().to_bits();
+        rand::srand(seed);
+
+        let mut file = BufWriter::new(File::create(path)?);
+        writeln!(file, "{}", REPLAY_HEADER)?;
+        writeln!(file, "{}", seed)?;
+        Ok(Self::Recording {
+            file,
+            frame_time: 0.,
+        })
+    }
+
+    fn play(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let mut lines = BufReader::new(File::open(path)?).lines();
+        if lines.next().transpose()?.as_deref() != Some(REPLAY_HEADER) {
+            bail!("Not a replay, or from another version");
+        }
+        let seed = lines
+            .next()
+            .transpose()?
+            .ok_or_else(|| anyhow!("Missing random seed"))?
+            .parse()?;
+        let frames = lines
+            .enumerate()
+            .map(|(i, line)| {
+                Frame::decode(&line?).map_err(|e| anyhow!("Bad frame {}: {}", i + 1, e))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        rand::srand(seed);
+        Ok(Self::Playing { frames, played: 0 })
+    }
+
+    pub fn is_playing(&self) -> bool {
+        matches!(self, Self::Playing { .. })
+    }
+
+    /// Time the coming frame takes, the recorded one during playback. Called once a frame before
+    /// the clocks advance.
+    pub fn frame_time(&mut self, live: f32) -> f32 {
+        match self {
+            Self::Off => live,
+            Self::Recording { frame_time, .. } => {
+                *frame_time = live;
+                live
+            }
+            Self::Playing { frames, played } => {
+                *played += 1;
+                match frames.get(*played - 1) {
+                    Some(frame) => frame.frame_time,
+                    None => {
+                        println!("Replay finished");
+                        *self = Self::Off;
+                        live
+                    }
+                }
+            }
+        }
+    }
+
+    /// Writes out whatever is still buffered of the recording.
+    pub fn finish(&mut self) {
+        if let Self::Recording { file, .. } = self {
+            if let Err(e) = file.flush() {
+                println!("Failed to save the recording: {}", e);
+            }
+        }
+    }
+}
+
+/// Whether the devices should be read this frame rather than a recording.
+pub fn live_input(replay: Res<InputReplay>) -> bool {
+    !replay.is_playing()
+}
+
+/// Saves the frame's input while recording, or swaps in the recorded input during playback. Runs
+/// after the devices are read and before anything uses the actions.
+pub fn replay_input(
+    mut replay: ResMut<InputReplay>,
+    mut actions: ResMut<Actions>,
+    mut mouse_input: ResMut<MouseInput>,
+) {
+    match &mut *replay {
+        InputReplay::Off => {}
+        InputReplay::Recording { file, frame_time } => {
+            // The frame that quits never reaches the simulation, so there's nothing to replay
+            if actions.pressed(Action::Quit) {
+                return;
+            }
+            let frame = Frame {
+                frame_time: *frame_time,
+                mouse_delta: mouse_input.mouse_delta,
+                actions: actions.clone(),
+            };
+            if let Err(e) = writeln!(file, "{}", frame.encode()) {
+                println!("Failed to record, stopping: {}", e);
+                *replay = InputReplay::Off;
+            }
+        }
+        InputReplay::Playing { frames, played } => {
+            if get_last_key_pressed().is_some() {
+                println!("Replay stopped");
+                *replay = InputReplay::Off;
+                *actions = Actions::default();
+                mouse_input.mouse_delta = Vec2::ZERO;
+                return;
+            }
+            if let Some(frame) = played.checked_sub(1).and_then(|i| frames.get(i)) {
+                *actions = frame.actions.clone();
+                mouse_input.mouse_delta = frame.mouse_delta;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_round_trip_exactly() {
+        let count = Action::ALL.len();
+        let values = (0..count).map(|i| (i as f32 / 7.).to_string());
+        let motions = (0..count).map(|i| (i as f32 * -13.3).to_string());
+        let pressed = (0..count).map(|i| (i % 2).to_string());
+        let line = values.chain(motions).chain(pressed).collect::<Vec<_>>();
+        let actions = Actions::decode(&line.join(" ")).unwrap();
+        let frame = Frame {
+            frame_time: 1. / 144.,
+            mouse_delta: vec2(-0.1, 1e-7),
+            actions,
+        };
+
+        let decoded = Frame::decode(&frame.encode()).unwrap();
+        assert_eq!(decoded.frame_time.to_bits(), frame.frame_time.to_bits());
+        assert_eq!(
+            decoded.mouse_delta.x.to_bits(),
+            frame.mouse_delta.x.to_bits()
+        );
+        assert_eq!(
+            decoded.mouse_delta.y.to_bits(),
+            frame.mouse_delta.y.to_bits()
+        );
+        assert_eq!(decoded.actions.encode(), frame.actions.encode());
+    }
+
+    #[test]
+    fn frames_reject_garbage() {
+        assert!(Frame::decode("").is_err());
+        assert!(Frame::decode("0.016 0 nan-ish").is_err());
+    }
+}
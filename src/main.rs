@@ -1,17 +1,49 @@
-use bevy_ecs::{prelude::*, world::World};
-use camera::camera::CameraState;
+use bevy_ecs::{prelude::*, schedule::ExecutorKind, world::World};
+use camera::{
+    camera::{CameraShake, CameraState},
+    spring::Spring,
+};
+use combat::{
+    damage::{DamageEvent, Health, Shield},
+    destruction::SpawnPoint,
+};
+use input::{
+    gamepad::Gamepads,
+    input_map::{Action, Actions, InputMap},
+    mouse::RawMouse,
+    rebind::BindingsEditor,
+    replay::InputReplay,
+};
 use macroquad::prelude::*;
-use macroquad::window::miniquad::*;
+use physics::{
+    collider::{Collider, ColliderKind},
+    collision::CollisionEvent,
+    rigid_body::RigidBody,
+};
+use renderer::renderer::MeshPipeline;
+use time::time::Time;
 use transform::transform::Transform;
-use utils::{input_utils::MouseInput, mesh_utils::{BaseMeshMaterial, Model}};
+use utils::{
+    asset_utils::AssetServer,
+    file_utils::{load_string, verify_assets},
+    input_utils::MouseInput,
+    mesh_utils::Model,
+    reload_utils::{HotReload, SHADER_FOLDER},
+};
 
 mod camera;
+mod combat;
+mod input;
+mod physics;
 mod player;
+mod projectiles;
 mod renderer;
+mod time;
 mod transform;
 mod utils;
 
-use crate::player::player::{Player, PlayerBundle};
+use crate::player::player::{FlightControls, Player, PlayerBundle};
+use crate::projectiles::projectile::Armament;
 
 fn conf() -> Conf {
     Conf {
@@ -24,175 +56,277 @@ fn conf() -> Conf {
 }
 #[macroquad::main(conf)]
 async fn main() {
-    let mut last_mouse_position: Vec2 = mouse_position().into();
-
     let mut grabbed = true;
     set_cursor_grab(grabbed);
     show_mouse(false);
 
-    let test_model = Model::new("test.obj", "res/").await;
+    for path in verify_assets() {
+        warn!("Asset {} failed its checksum", path);
+    }
+
+    let mut assets = AssetServer::default();
+    let test_model = Model::new("test.obj", "res/", &mut assets).await;
 
     let mut world = World::new();
 
+    let spawn = Transform {
+        position: vec3(0., 1., 0.),
+        scale: Vec3::ONE * 3.,
+        rotation: Quat::IDENTITY,
+    };
     let player = PlayerBundle {
         player: Player::new(BLUE, 200.),
-        model: test_model,
-        transform: Transform {
-            position: vec3(0., 1., 0.),
-            scale: Vec3::ONE * 3.,
-            rotation: Quat::IDENTITY,
-        },
+        armament: Armament::new(vec![vec3(-0.5, 0., 1.), vec3(0.5, 0., 1.)], &mut assets).await,
+        model: test_model.clone(),
+        collider: Collider::from_model(&test_model, ColliderKind::Obb),
+        body: RigidBody::new(5., 3.),
+        controls: FlightControls::new(2.),
+        transform: spawn,
+        health: Health::new(100.),
+        shield: Shield::new(50., 10., 3.),
+        spawn: SpawnPoint(spawn),
     };
 
-    let world_up = vec3(0.0, 1.0, 0.0);
-    let yaw: f32 = 1.18;
-    let pitch: f32 = 0.0;
-    let roll: f32 = 0.0;
-
-    let front = vec3(
-        yaw.cos() * pitch.cos(),
-        pitch.sin(),
-        yaw.sin() * pitch.cos(),
-    )
-    .normalize();
-    let right = front.cross(world_up).normalize();
-    let up = right.cross(front).normalize();
-    let camera = CameraState::new(
-        vec3(0., 0., -50.),
-        player.transform.position + vec3(0., 0., -50.),
-        front,
-        right,
-        up,
-        yaw,
-        pitch,
-        roll,
+    // Slightly underdamped so the camera sways a little after hard manoeuvres
+    let camera = CameraState::new(vec3(0., 8., -50.)).with_springs(
+        Spring {
+            stiffness: 80.,
+            damping: 14.,
+        },
+        Spring::critically_damped(120.),
     );
     world.spawn(player);
     world.insert_resource(camera);
 
-    world.spawn((
-        Model::new("test.obj", "res/").await,
-        Transform {
-            position: vec3(10., 0., 0.),
-            scale: Vec3::ONE * 10.,
-            rotation: Quat::IDENTITY,
-        }
-    ));
-    world.spawn((
-        Model::new("test.obj", "res/").await,
-        Transform {
-            position: vec3(100., 0., 60.),
-            scale: Vec3::ONE * 20.,
-            rotation: Quat::IDENTITY,
-        }
-    ));
-    world.spawn((
-        Model::new("test.obj", "res/").await,
-        Transform {
-            position: vec3(500., 300., 500.),
-            scale: Vec3::ONE * 200.,
-            rotation: Quat::IDENTITY,
-        }
-    ));
-    world.spawn((
-        Model::new("test.obj", "res/").await,
-        Transform {
-            position: vec3(100., 10., 600.),
-            scale: Vec3::ONE * 100.,
-            rotation: Quat::IDENTITY,
-        }
-    ));
-    world.spawn((
-        Model::new("test.obj", "res/").await,
-        Transform {
-            position: vec3(100., 100., 0.),
-            scale: Vec3::ONE * 1000.,
-            rotation: Quat::IDENTITY,
-        }
-    ));
+    for (position, scale) in [
+        (vec3(10., 0., 0.), 10.),
+        (vec3(100., 0., 60.), 20.),
+        (vec3(500., 300., 500.), 200.),
+        (vec3(100., 10., 600.), 100.),
+        (vec3(100., 100., 0.), 1000.),
+    ] {
+        world.spawn((
+            test_model.clone(),
+            Collider::from_model(&test_model, ColliderKind::ConvexHull),
+            RigidBody::new(scale * scale * scale * ASTEROID_DENSITY, scale).with_drag(0.1, 0.5),
+            Health::new(scale * ASTEROID_TOUGHNESS),
+            Transform {
+                position,
+                scale: Vec3::ONE * scale,
+                rotation: Quat::IDENTITY,
+            },
+        ));
+    }
 
     let mouse_input: MouseInput = MouseInput {
         mouse_delta: Vec2::ZERO,
     };
     world.insert_resource(mouse_input);
-
-    // Create a new Schedule, which defines an execution sptrategy for Systems
+    world.insert_resource(InputMap::load());
+    world.insert_resource(Actions::default());
+    world.insert_non_send_resource(Gamepads::new());
+    world.insert_resource(RawMouse::new());
+    world.insert_resource(BindingsEditor::default());
+    world.insert_resource(InputReplay::from_args());
+    world.insert_resource(assets);
+    world.insert_resource(Events::<CollisionEvent>::default());
+    world.insert_resource(Events::<DamageEvent>::default());
+    world.insert_resource(Events::<CameraShake>::default());
+    world.insert_resource(Time::default());
+
+    // Sampled once a frame before the simulation catches up. Both this and the simulation run
+    // their systems one at a time in a fixed order, so replays play out the same way.
+    let mut input_schedule = Schedule::default();
+    input_schedule.set_executor_kind(ExecutorKind::SingleThreaded);
+    input_schedule.add_systems(
+        input::mouse::read_mouse
+            .before(input::input_map::read_actions)
+            .run_if(input::replay::live_input),
+    );
+    input_schedule.add_systems(input::input_map::read_actions.run_if(input::replay::live_input));
+    input_schedule.add_systems(
+        input::rebind::edit_bindings
+            .after(input::input_map::read_actions)
+            .run_if(input::replay::live_input),
+    );
+    input_schedule.add_systems(input::replay::replay_input.after(input::input_map::read_actions));
+    input_schedule.add_systems(
+        camera::camera::camera_input
+            .after(input::replay::replay_input)
+            .before(player::player::player_input),
+    );
+    input_schedule.add_systems(player::player::player_input.after(input::replay::replay_input));
+    input_schedule
+        .add_systems(projectiles::projectile::select_weapon.after(input::replay::replay_input));
+
+    // Simulation, run zero or more times a frame at a fixed rate
+    let mut fixed_schedule = Schedule::default();
+    fixed_schedule.set_executor_kind(ExecutorKind::SingleThreaded);
+    fixed_schedule.add_systems(
+        transform::transform::save_previous_transforms.before(player::player::fly_ship),
+    );
+    fixed_schedule.add_systems(player::player::regenerate_energy.before(player::player::fly_ship));
+    fixed_schedule.add_systems(player::player::fly_ship);
+    fixed_schedule
+        .add_systems(projectiles::projectile::fire_weapons.after(player::player::fly_ship));
+    fixed_schedule.add_systems(
+        projectiles::projectile::steer_homing.after(projectiles::projectile::fire_weapons),
+    );
+    fixed_schedule.add_systems(
+        physics::rigid_body::integrate_bodies
+            .after(player::player::fly_ship)
+            .after(projectiles::projectile::steer_homing),
+    );
+    fixed_schedule.add_systems(
+        projectiles::bullets::update_bullets.after(physics::rigid_body::integrate_bodies),
+    );
+    fixed_schedule.add_systems(
+        bevy_ecs::event::event_update_system::<CollisionEvent>
+            .before(physics::collision::detect_collisions),
+    );
+    fixed_schedule.add_systems(
+        physics::collision::detect_collisions
+            .after(physics::rigid_body::integrate_bodies)
+            .after(projectiles::bullets::update_bullets),
+    );
+    fixed_schedule.add_systems(
+        physics::rigid_body::resolve_collisions.after(physics::collision::detect_collisions),
+    );
+    fixed_schedule
+        .add_systems(combat::damage::projectile_hits.after(physics::collision::detect_collisions));
+    fixed_schedule.add_systems(
+        bevy_ecs::event::event_update_system::<DamageEvent>
+            .before(physics::rigid_body::resolve_collisions)
+            .before(combat::damage::projectile_hits),
+    );
+    fixed_schedule.add_systems(
+        combat::damage::apply_damage
+            .after(physics::rigid_body::resolve_collisions)
+            .after(combat::damage::projectile_hits),
+    );
+    fixed_schedule.add_systems(
+        combat::damage::regenerate_shields
+            .after(player::player::regenerate_energy)
+            .before(combat::damage::apply_damage),
+    );
+    fixed_schedule
+        .add_systems(combat::destruction::destroy_dead.after(combat::damage::apply_damage));
+    fixed_schedule
+        .add_systems(combat::destruction::kill_player.after(combat::damage::apply_damage));
+    fixed_schedule
+        .add_systems(combat::destruction::respawn_player.before(combat::destruction::kill_player));
+    fixed_schedule.add_systems(combat::destruction::update_debris);
+
+    // Rendering and anything else that should happen exactly once a frame
     let mut schedule = Schedule::default();
-    schedule.add_systems(camera::camera::update_camera.before(player::player::player_input));
-    schedule.add_systems(player::player::player_input.before(player::player::update_player));
-    schedule.add_systems(player::player::update_player);
-    schedule.add_systems(utils::mesh_utils::draw_models.after(player::player::update_player));
-    schedule.add_systems(camera::camera::reset_camera.after(utils::mesh_utils::draw_models));
-
-    let dither_material = load_material(
-        ShaderSource::Glsl {
-            vertex: DITHER_VERTEX_SHADER,
-            fragment: DITHER_FRAGMENT_SHADER,
-        },
-        MaterialParams {
-            ..Default::default()
-        },
+    schedule.add_systems(
+        bevy_ecs::event::event_update_system::<CameraShake>.before(camera::camera::update_camera),
+    );
+    schedule.add_systems(camera::camera::update_camera);
+    schedule.add_systems(utils::mesh_utils::draw_models.after(camera::camera::update_camera));
+    schedule.add_systems(projectiles::bullets::draw_bullets.after(camera::camera::update_camera));
+    schedule.add_systems(
+        camera::camera::reset_camera
+            .after(utils::mesh_utils::draw_models)
+            .after(projectiles::bullets::draw_bullets),
+    );
+    schedule.add_systems(
+        projectiles::projectile::draw_armament_hud.after(camera::camera::reset_camera),
+    );
+    schedule.add_systems(player::player::draw_player_hud.after(camera::camera::reset_camera));
+    schedule.add_systems(camera::camera::draw_camera_hud.after(camera::camera::reset_camera));
+    schedule.add_systems(input::rebind::draw_bindings_editor.after(camera::camera::reset_camera));
+    schedule.add_systems(utils::asset_utils::unload_unused_assets);
+
+    let mut dither_material = load_dither_material(
+        &load_string(DITHER_VERTEX_SHADER, SHADER_FOLDER)
+            .await
+            .unwrap(),
+        &load_string(DITHER_FRAGMENT_SHADER, SHADER_FOLDER)
+            .await
+            .unwrap(),
     )
     .unwrap();
 
-    let mesh_material = load_material(
-        ShaderSource::Glsl {
-            vertex: utils::mesh_utils::MESH_VERTEX_SHADER,
-            fragment: utils::mesh_utils::MESH_FRAGMENT_SHADER,
-        },
-        MaterialParams {
-            uniforms: vec![
-                ("LightColor".to_owned(), UniformType::Float3),
-                ("ObjectColor".to_owned(), UniformType::Float3),
-                ("ModelPos".to_owned(), UniformType::Float3),
-            ],
-            pipeline_params: PipelineParams {
-                color_blend: Some(BlendState::new(
-                    Equation::Add,
-                    BlendFactor::Value(BlendValue::SourceAlpha),
-                    BlendFactor::OneMinusValue(BlendValue::SourceAlpha),
-                )),
-                depth_write: true,
-                depth_test: Comparison::LessOrEqual,
-                ..Default::default()
-            },
-            ..Default::default()
-        },
+    let mesh_pipeline = MeshPipeline::new(
+        &load_string(MESH_VERTEX_SHADER, SHADER_FOLDER)
+            .await
+            .unwrap(),
+        &load_string(MESH_FRAGMENT_SHADER, SHADER_FOLDER)
+            .await
+            .unwrap(),
+        vec3(1.0, 0.8, 0.4),
+        vec3(1., 1., 1.),
     )
     .unwrap();
+    world.insert_resource(mesh_pipeline);
 
-    mesh_material.set_uniform("LightColor", (1.0f32, 0.8f32, 0.4f32));
-    mesh_material.set_uniform("ObjectColor", (1f32, 1f32, 1f32));
-
-    let base_mesh_material = BaseMeshMaterial { material: mesh_material };
-    
-    world.insert_resource(base_mesh_material);
+    let mut hot_reload = HotReload::new();
 
     loop {
-        if is_key_pressed(KeyCode::Escape) {
+        if let Some(hot_reload) = &mut hot_reload {
+            let changed = hot_reload.changed_files();
+            if !changed.is_empty() {
+                hot_reload.reload_models(&mut world, &changed).await;
+
+                let mut mesh_pipeline = world.resource_mut::<MeshPipeline>();
+                hot_reload
+                    .reload_shader(
+                        &changed,
+                        MESH_VERTEX_SHADER,
+                        MESH_FRAGMENT_SHADER,
+                        |vertex, fragment| mesh_pipeline.reload_shader(vertex, fragment),
+                    )
+                    .await;
+                if let Some(material) = hot_reload
+                    .reload_shader(
+                        &changed,
+                        DITHER_VERTEX_SHADER,
+                        DITHER_FRAGMENT_SHADER,
+                        load_dither_material,
+                    )
+                    .await
+                {
+                    dither_material = material;
+                }
+            }
+        }
+
+        let frame_time = world
+            .resource_mut::<InputReplay>()
+            .frame_time(get_frame_time());
+        let steps = world.resource_mut::<Time>().advance(frame_time);
+        input_schedule.run(&mut world);
+
+        let actions = world.resource::<Actions>();
+        if actions.pressed(Action::Quit) {
+            world.resource_mut::<InputReplay>().finish();
             break;
         }
-        if is_key_pressed(KeyCode::Tab) {
+        if actions.pressed(Action::ToggleGrab) {
             grabbed = !grabbed;
             set_cursor_grab(grabbed);
             show_mouse(!grabbed);
+            world.resource_mut::<RawMouse>().reset();
         }
 
-        let mouse_position: Vec2 = mouse_position().into();
-        world.get_resource_mut::<MouseInput>().unwrap().mouse_delta =
-            mouse_position - last_mouse_position;
-        last_mouse_position = mouse_position;
+        for _ in 0..steps {
+            fixed_schedule.run(&mut world);
+        }
 
         clear_background(BLACK);
 
-        //b.draw_m(&player.get_pos(), &front);
-        //b.update(delta);
         // Back to screen space, render some text
 
         schedule.run(&mut world);
 
         draw_text(
-            format!("Press <TAB> to toggle mouse grab: {}", grabbed).as_str(),
+            format!(
+                "Press <{}> to toggle mouse grab: {}",
+                world.resource::<InputMap>().label(Action::ToggleGrab),
+                grabbed
+            )
+            .as_str(),
             10.0,
             48.0 + 0.0,
             30.0,
@@ -216,79 +350,29 @@ async fn main() {
         );
         gl_use_default_material();
 
-        next_frame().await
-    }
-}
-
-/// Testing Screen Shaders
-const DITHER_FRAGMENT_SHADER: &'static str = r#"#version 150
-precision mediump float;
-
-in vec2 vUv;
-out vec4 diffuseColor;
-
-uniform sampler2D _ScreenTexture;
-
-float luma(vec3 color) {
-    return dot(color, vec3(0.299, 0.587, 0.114));
-}
-
-float luma(vec4 color) {
-    return dot(color.rgb, vec3(0.299, 0.587, 0.114));
-}
-
-float dither4x4(vec2 position, float brightness) {
-    int x = int(mod(position.x, 4.0));
-    int y = int(mod(position.y, 4.0));
-    int index = x + y * 4;
-    float limit = 0.0;
+        if let Some(hot_reload) = &hot_reload {
+            hot_reload.draw_error();
+        }
 
-    if (x < 8) {
-        if (index == 0) limit = 0.0625;
-        if (index == 1) limit = 0.5625;
-        if (index == 2) limit = 0.1875;
-        if (index == 3) limit = 0.6875;
-        if (index == 4) limit = 0.8125;
-        if (index == 5) limit = 0.3125;
-        if (index == 6) limit = 0.9375;
-        if (index == 7) limit = 0.4375;
-        if (index == 8) limit = 0.25;
-        if (index == 9) limit = 0.75;
-        if (index == 10) limit = 0.125;
-        if (index == 11) limit = 0.625;
-        if (index == 12) limit = 1.0;
-        if (index == 13) limit = 0.5;
-        if (index == 14) limit = 0.875;
-        if (index == 15) limit = 0.375;
+        next_frame().await
     }
-
-    return brightness < limit ? 0.92 : 1.0;
-}
-
-vec3 dither4x4(vec2 position, vec3 color) {
-    return color * dither4x4(position, luma(color));
 }
 
-vec4 dither4x4(vec2 position, vec4 color) {
-    return vec4(color.rgb * dither4x4(position, luma(color)), color.a);
-}
-
-void main() {
-    diffuseColor = dither4x4(
-        gl_FragCoord.xy
-      , texture(_ScreenTexture, vUv)
-    );
-}
-"#;
-
-const DITHER_VERTEX_SHADER: &'static str = "#version 150
-precision mediump float;
+const ASTEROID_DENSITY: f32 = 0.01;
+/// Health per unit of asteroid scale.
+const ASTEROID_TOUGHNESS: f32 = 5.;
+const MESH_VERTEX_SHADER: &str = "mesh.vert.glsl";
+const MESH_FRAGMENT_SHADER: &str = "mesh.frag.glsl";
 
-in vec2 position;
-out vec2 vUv;
+/// Testing Screen Shaders
+const DITHER_VERTEX_SHADER: &str = "dither.vert.glsl";
+const DITHER_FRAGMENT_SHADER: &str = "dither.frag.glsl";
 
-void main() {
-  vUv = (position + vec2(1.0)) / 2.0;
-  gl_Position = vec4(position, 1.0, 1.0);
+fn load_dither_material(vertex: &str, fragment: &str) -> anyhow::Result<Material> {
+    Ok(load_material(
+        ShaderSource::Glsl { vertex, fragment },
+        MaterialParams {
+            ..Default::default()
+        },
+    )?)
 }
-";
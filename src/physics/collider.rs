@@ -0,0 +1,206 @@
+use std::collections::HashSet;
+
+use bevy_ecs::component::Component;
+use macroquad::prelude::*;
+
+use crate::{transform::transform::Transform, utils::mesh_utils::Model};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ColliderKind {
+    Sphere,
+    Aabb,
+    Obb,
+    ConvexHull,
+}
+
+/// Collision shape in model space, scaled and rotated into the world by the entity's `Transform`.
+#[derive(Clone, Debug)]
+pub enum Shape {
+    Sphere {
+        center: Vec3,
+        radius: f32,
+    },
+    /// Box that stays axis aligned in the world, growing to fit the rotated model bounds.
+    Aabb {
+        min: Vec3,
+        max: Vec3,
+    },
+    /// Box that rotates with the entity.
+    Obb {
+        center: Vec3,
+        half_extents: Vec3,
+    },
+    /// Unique model vertices. Support points over them are the same as over their convex hull,
+    /// so the hull itself never has to be built.
+    ConvexHull(Vec<Vec3>),
+}
+
+#[derive(Component, Clone, Debug)]
+pub struct Collider {
+    pub shape: Shape,
+}
+
+/// Marks colliders that report collisions without being pushed apart, like bullets.
+#[derive(Component)]
+pub struct Sensor;
+
+impl Collider {
+    /// Fits a shape of the given kind around every vertex of the model.
+    pub fn from_model(model: &Model, kind: ColliderKind) -> Self {
+        let points = model
+            .meshes()
+            .iter()
+            .flat_map(|mesh| mesh.vertices.iter().map(|v| v.position))
+            .collect::<Vec<_>>();
+        Self::from_points(&points, kind)
+    }
+
+    pub fn from_points(points: &[Vec3], kind: ColliderKind) -> Self {
+        let (min, max) = points
+            .iter()
+            .fold((Vec3::INFINITY, Vec3::NEG_INFINITY), |(min, max), p| {
+                (min.min(*p), max.max(*p))
+            });
+        let (min, max) = if points.is_empty() {
+            (Vec3::ZERO, Vec3::ZERO)
+        } else {
+            (min, max)
+        };
+        let center = (min + max) / 2.;
+
+        let shape = match kind {
+            ColliderKind::Sphere => Shape::Sphere {
+                center,
+                radius: points.iter().map(|p| p.distance(center)).fold(0., f32::max),
+            },
+            ColliderKind::Aabb => Shape::Aabb { min, max },
+            ColliderKind::Obb => Shape::Obb {
+                center,
+                half_extents: (max - min) / 2.,
+            },
+            ColliderKind::ConvexHull => {
+                let mut seen = HashSet::new();
+                let unique = points
+                    .iter()
+                    .filter(|p| seen.insert(p.to_array().map(f32::to_bits)))
+                    .copied()
+                    .collect::<Vec<_>>();
+                Shape::ConvexHull(if unique.is_empty() {
+                    vec![Vec3::ZERO]
+                } else {
+                    unique
+                })
+            }
+        };
+
+        Self { shape }
+    }
+
+    pub fn kind(&self) -> ColliderKind {
+        match self.shape {
+            Shape::Sphere { .. } => ColliderKind::Sphere,
+            Shape::Aabb { .. } => ColliderKind::Aabb,
+            Shape::Obb { .. } => ColliderKind::Obb,
+            Shape::ConvexHull(_) => ColliderKind::ConvexHull,
+        }
+    }
+
+    /// Places the shape in the world so it can be tested against others.
+    pub fn in_world<'a>(&'a self, transform: &Transform) -> WorldCollider<'a> {
+        let matrix = transform.model_matrix();
+        let aabb = match &self.shape {
+            Shape::Aabb { min, max } => transformed_bounds(&matrix, *min, *max),
+            _ => (Vec3::ZERO, Vec3::ZERO),
+        };
+        let mut collider = WorldCollider {
+            shape: &self.shape,
+            matrix,
+            linear_transpose: Mat3::from_mat4(matrix).transpose(),
+            min: aabb.0,
+            max: aabb.1,
+        };
+        if !matches!(self.shape, Shape::Aabb { .. }) {
+            collider.min = -collider.support_axes(-Vec3::ONE);
+            collider.max = collider.support_axes(Vec3::ONE);
+        }
+        collider
+    }
+}
+
+/// A collider together with the transform it is currently placed at.
+pub struct WorldCollider<'a> {
+    shape: &'a Shape,
+    matrix: Mat4,
+    linear_transpose: Mat3,
+    /// World space bounds, used by the broad phase.
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl WorldCollider<'_> {
+    /// Point of the shape furthest along the given world direction.
+    pub fn support(&self, direction: Vec3) -> Vec3 {
+        if let Shape::Aabb { .. } = self.shape {
+            return vec3(
+                if direction.x >= 0. {
+                    self.max.x
+                } else {
+                    self.min.x
+                },
+                if direction.y >= 0. {
+                    self.max.y
+                } else {
+                    self.min.y
+                },
+                if direction.z >= 0. {
+                    self.max.z
+                } else {
+                    self.min.z
+                },
+            );
+        }
+
+        // Maximising dot(d, M * p) is the same as maximising dot(M^T * d, p) in model space
+        let local = self.linear_transpose * direction;
+        let point = match self.shape {
+            Shape::Sphere { center, radius } => *center + local.normalize_or_zero() * *radius,
+            Shape::Obb {
+                center,
+                half_extents,
+            } => *center + *half_extents * local.signum(),
+            Shape::ConvexHull(points) => *points
+                .iter()
+                .max_by(|a, b| a.dot(local).total_cmp(&b.dot(local)))
+                .unwrap(),
+            Shape::Aabb { .. } => unreachable!(),
+        };
+        self.matrix.transform_point3(point)
+    }
+
+    /// Furthest extent along each world axis separately, signed by `axes`.
+    fn support_axes(&self, axes: Vec3) -> Vec3 {
+        vec3(
+            self.support(Vec3::X * axes.x).x * axes.x,
+            self.support(Vec3::Y * axes.y).y * axes.y,
+            self.support(Vec3::Z * axes.z).z * axes.z,
+        )
+    }
+
+    pub fn bounds_overlap(&self, other: &WorldCollider) -> bool {
+        self.min.cmple(other.max).all() && other.min.cmple(self.max).all()
+    }
+}
+
+fn transformed_bounds(matrix: &Mat4, min: Vec3, max: Vec3) -> (Vec3, Vec3) {
+    let mut bounds = (Vec3::INFINITY, Vec3::NEG_INFINITY);
+    for i in 0..8 {
+        let corner = vec3(
+            if i & 1 == 0 { min.x } else { max.x },
+            if i & 2 == 0 { min.y } else { max.y },
+            if i & 4 == 0 { min.z } else { max.z },
+        );
+        let world = matrix.transform_point3(corner);
+        bounds = (bounds.0.min(world), bounds.1.max(world));
+    }
+    bounds
+}
@@ -0,0 +1,378 @@
+use std::collections::{HashMap, HashSet};
+
+use bevy_ecs::{
+    entity::Entity,
+    event::{Event, EventWriter},
+    system::Query,
+};
+use macroquad::prelude::*;
+
+use crate::{
+    physics::collider::{Collider, WorldCollider},
+    transform::transform::Transform,
+};
+
+/// Size of a cell in the broad phase's spatial hash, roughly the size of a typical collider.
+const CELL_SIZE: f32 = 20.;
+/// Colliders covering more cells than this are checked against everything instead of hashed.
+const MAX_CELLS: i32 = 64;
+const MAX_ITERATIONS: usize = 64;
+const EPA_TOLERANCE: f32 = 0.001;
+
+/// Sent every fixed step two colliders overlap.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct CollisionEvent {
+    pub a: Entity,
+    pub b: Entity,
+    /// Direction from `a` into `b`. Moving `a` by `-normal * depth` separates the two.
+    pub normal: Vec3,
+    pub depth: f32,
+    /// Approximate contact point, halfway between the deepest points of the two shapes.
+    pub point: Vec3,
+}
+
+pub fn detect_collisions(
+    query: Query<(Entity, &Collider, &Transform)>,
+    mut events: EventWriter<CollisionEvent>,
+) {
+    let bodies = query
+        .iter()
+        .map(|(entity, collider, transform)| (entity, collider.in_world(transform)))
+        .collect::<Vec<_>>();
+
+    for (i, j) in broad_phase(&bodies) {
+        let (a, collider_a) = &bodies[i];
+        let (b, collider_b) = &bodies[j];
+        if let Some((normal, depth)) = intersect(collider_a, collider_b) {
+            events.send(CollisionEvent {
+                a: *a,
+                b: *b,
+                normal,
+                depth,
+                point: (collider_a.support(normal) + collider_b.support(-normal)) / 2.,
+            });
+        }
+    }
+}
+
+/// Pairs of indices whose bounds overlap, found by bucketing the bounds into a spatial hash.
+fn broad_phase(bodies: &[(Entity, WorldCollider)]) -> Vec<(usize, usize)> {
+    let cell = |p: Vec3| (p / CELL_SIZE).floor().as_ivec3();
+
+    let mut grid: HashMap<IVec3, Vec<usize>> = HashMap::new();
+    let mut oversized = Vec::new();
+    for (i, (_, collider)) in bodies.iter().enumerate() {
+        let (min, max) = (cell(collider.min), cell(collider.max));
+        let size = max - min + IVec3::ONE;
+        if size.x.saturating_mul(size.y).saturating_mul(size.z) > MAX_CELLS {
+            oversized.push(i);
+            continue;
+        }
+        for x in min.x..=max.x {
+            for y in min.y..=max.y {
+                for z in min.z..=max.z {
+                    grid.entry(ivec3(x, y, z)).or_default().push(i);
+                }
+            }
+        }
+    }
+
+    let mut pairs = HashSet::new();
+    let mut check = |i: usize, j: usize| {
+        if i != j && bodies[i].1.bounds_overlap(&bodies[j].1) {
+            pairs.insert((i.min(j), i.max(j)));
+        }
+    };
+    for indices in grid.values() {
+        for (n, &i) in indices.iter().enumerate() {
+            for &j in &indices[n + 1..] {
+                check(i, j);
+            }
+        }
+    }
+    for &i in &oversized {
+        for j in 0..bodies.len() {
+            check(i, j);
+        }
+    }
+    // Sorted so collisions resolve in the same order every run, whatever the hash seeds
+    let mut pairs = pairs.into_iter().collect::<Vec<_>>();
+    pairs.sort_unstable();
+    pairs
+}
+
+/// Narrow phase, GJK to find whether the shapes overlap followed by EPA for the penetration
+/// normal and depth.
+pub fn intersect(a: &WorldCollider, b: &WorldCollider) -> Option<(Vec3, f32)> {
+    let support = |direction: Vec3| a.support(direction) - b.support(-direction);
+    let simplex = gjk(&support)?;
+    epa(&support, simplex)
+}
+
+fn same_direction(a: Vec3, b: Vec3) -> bool {
+    a.dot(b) > 0.
+}
+
+/// Returns a tetrahedron of the Minkowski difference enclosing the origin if the shapes overlap.
+/// The simplex is kept with the newest point first.
+fn gjk(support: &impl Fn(Vec3) -> Vec3) -> Option<[Vec3; 4]> {
+    let first = support(Vec3::X);
+    let mut simplex = vec![first];
+    let mut direction = -first;
+
+    for _ in 0..MAX_ITERATIONS {
+        if direction.length_squared() < f32::EPSILON {
+            // The origin is on the simplex itself, which counts as touching rather than overlapping
+            return None;
+        }
+        let point = support(direction);
+        if point.dot(direction) <= 0. {
+            return None;
+        }
+        simplex.insert(0, point);
+        if next_simplex(&mut simplex, &mut direction) {
+            return Some([simplex[0], simplex[1], simplex[2], simplex[3]]);
+        }
+    }
+    None
+}
+
+fn next_simplex(simplex: &mut Vec<Vec3>, direction: &mut Vec3) -> bool {
+    match simplex.len() {
+        2 => line(simplex, direction),
+        3 => triangle(simplex, direction),
+        _ => tetrahedron(simplex, direction),
+    }
+}
+
+fn line(simplex: &mut Vec<Vec3>, direction: &mut Vec3) -> bool {
+    let (a, b) = (simplex[0], simplex[1]);
+    let ab = b - a;
+    let ao = -a;
+
+    if same_direction(ab, ao) {
+        *direction = ab.cross(ao).cross(ab);
+        if direction.length_squared() < f32::EPSILON {
+            // Origin on the line, any perpendicular direction will do
+            *direction = ab.any_orthonormal_vector();
+        }
+    } else {
+        *simplex = vec![a];
+        *direction = ao;
+    }
+    false
+}
+
+fn triangle(simplex: &mut Vec<Vec3>, direction: &mut Vec3) -> bool {
+    let (a, b, c) = (simplex[0], simplex[1], simplex[2]);
+    let ab = b - a;
+    let ac = c - a;
+    let ao = -a;
+    let abc = ab.cross(ac);
+
+    if same_direction(abc.cross(ac), ao) {
+        if same_direction(ac, ao) {
+            *simplex = vec![a, c];
+            *direction = ac.cross(ao).cross(ac);
+        } else {
+            *simplex = vec![a, b];
+            return line(simplex, direction);
+        }
+    } else if same_direction(ab.cross(abc), ao) {
+        *simplex = vec![a, b];
+        return line(simplex, direction);
+    } else if same_direction(abc, ao) {
+        *direction = abc;
+    } else {
+        *simplex = vec![a, c, b];
+        *direction = -abc;
+    }
+    false
+}
+
+fn tetrahedron(simplex: &mut Vec<Vec3>, direction: &mut Vec3) -> bool {
+    let (a, b, c, d) = (simplex[0], simplex[1], simplex[2], simplex[3]);
+    let ab = b - a;
+    let ac = c - a;
+    let ad = d - a;
+    let ao = -a;
+
+    if same_direction(ab.cross(ac), ao) {
+        *simplex = vec![a, b, c];
+        return triangle(simplex, direction);
+    }
+    if same_direction(ac.cross(ad), ao) {
+        *simplex = vec![a, c, d];
+        return triangle(simplex, direction);
+    }
+    if same_direction(ad.cross(ab), ao) {
+        *simplex = vec![a, d, b];
+        return triangle(simplex, direction);
+    }
+    true
+}
+
+/// Expands the GJK tetrahedron towards the Minkowski difference's surface until the face closest
+/// to the origin is found.
+fn epa(support: &impl Fn(Vec3) -> Vec3, simplex: [Vec3; 4]) -> Option<(Vec3, f32)> {
+    let mut polytope = simplex.to_vec();
+    let mut faces = vec![[0, 1, 2], [0, 3, 1], [0, 2, 3], [1, 3, 2]];
+
+    for _ in 0..MAX_ITERATIONS {
+        let normals = face_normals(&polytope, &mut faces);
+        let (closest, &(normal, distance)) = normals
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| a.1.total_cmp(&b.1))?;
+
+        let point = support(normal);
+        if normal.dot(point) - distance < EPA_TOLERANCE {
+            return Some((normal, distance + EPA_TOLERANCE));
+        }
+
+        // Remove every face the new point can see and stitch the hole's edges to it
+        let mut edges: Vec<(usize, usize)> = Vec::new();
+        let mut kept = Vec::new();
+        for (face, (normal, _)) in faces.iter().zip(&normals) {
+            if same_direction(*normal, point - polytope[face[0]]) {
+                for edge in [(face[0], face[1]), (face[1], face[2]), (face[2], face[0])] {
+                    match edges.iter().position(|e| *e == (edge.1, edge.0)) {
+                        Some(shared) => {
+                            edges.swap_remove(shared);
+                        }
+                        None => edges.push(edge),
+                    }
+                }
+            } else {
+                kept.push(*face);
+            }
+        }
+        if kept.len() == faces.len() {
+            // The closest face can't be expanded any further
+            return Some((normals[closest].0, distance + EPA_TOLERANCE));
+        }
+
+        let index = polytope.len();
+        polytope.push(point);
+        faces = kept;
+        faces.extend(edges.into_iter().map(|(a, b)| [a, b, index]));
+    }
+    None
+}
+
+/// Outward facing unit normal of each face and its distance from the origin. Faces wound the
+/// wrong way round are flipped, so that their edges line up with their neighbours' when stitching.
+fn face_normals(polytope: &[Vec3], faces: &mut [[usize; 3]]) -> Vec<(Vec3, f32)> {
+    faces
+        .iter_mut()
+        .map(|face| {
+            let [a, b, c] = face.map(|i| polytope[i]);
+            let normal = (b - a).cross(c - a).normalize_or_zero();
+            if normal == Vec3::ZERO {
+                return (normal, f32::INFINITY);
+            }
+            let distance = normal.dot(a);
+            if distance < 0. {
+                face.swap(1, 2);
+                (-normal, -distance)
+            } else {
+                (normal, distance)
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physics::collider::Shape;
+
+    fn collider(shape: Shape) -> Collider {
+        Collider { shape }
+    }
+
+    fn at(position: Vec3) -> Transform {
+        Transform {
+            position,
+            scale: Vec3::ONE,
+            rotation: Quat::IDENTITY,
+        }
+    }
+
+    fn assert_penetration(a: &WorldCollider, b: &WorldCollider, normal: Vec3, depth: f32) {
+        let (found_normal, found_depth) = intersect(a, b).expect("Shapes should overlap");
+        assert!(
+            found_normal.dot(normal) > 0.99,
+            "normal {} instead of {}",
+            found_normal,
+            normal
+        );
+        assert!(
+            (found_depth - depth).abs() < 0.02,
+            "depth {} instead of {}",
+            found_depth,
+            depth
+        );
+    }
+
+    #[test]
+    fn overlapping_spheres_push_apart_along_their_centers() {
+        let sphere = collider(Shape::Sphere {
+            center: Vec3::ZERO,
+            radius: 1.,
+        });
+        let a = sphere.in_world(&at(Vec3::ZERO));
+        for direction in [Vec3::X, Vec3::NEG_Y, vec3(1., 1., -1.).normalize()] {
+            let b = sphere.in_world(&at(direction * 1.5));
+            assert_penetration(&a, &b, direction, 0.5);
+        }
+    }
+
+    #[test]
+    fn sphere_against_box_faces() {
+        let cube = collider(Shape::Obb {
+            center: Vec3::ZERO,
+            half_extents: Vec3::ONE,
+        });
+        let sphere = collider(Shape::Sphere {
+            center: Vec3::ZERO,
+            radius: 1.,
+        });
+        let a = cube.in_world(&at(Vec3::ZERO));
+        for direction in [
+            Vec3::X,
+            Vec3::NEG_X,
+            Vec3::Y,
+            Vec3::NEG_Y,
+            Vec3::Z,
+            Vec3::NEG_Z,
+        ] {
+            // Off center across the face, so the deepest point isn't on a symmetry axis
+            let offset = direction * 1.75 + direction.any_orthonormal_vector() * 0.3;
+            let b = sphere.in_world(&at(offset));
+            assert_penetration(&a, &b, direction, 0.25);
+        }
+    }
+
+    #[test]
+    fn boxes_push_apart_along_the_shallowest_axis() {
+        let cube = collider(Shape::Obb {
+            center: Vec3::ZERO,
+            half_extents: Vec3::ONE,
+        });
+        let a = cube.in_world(&at(Vec3::ZERO));
+        let b = cube.in_world(&at(vec3(0.5, -1.8, 0.25)));
+        assert_penetration(&a, &b, Vec3::NEG_Y, 0.2);
+    }
+
+    #[test]
+    fn separated_shapes_dont_intersect() {
+        let sphere = collider(Shape::Sphere {
+            center: Vec3::ZERO,
+            radius: 1.,
+        });
+        let a = sphere.in_world(&at(Vec3::ZERO));
+        let b = sphere.in_world(&at(vec3(1.5, 1.5, 0.)));
+        assert!(intersect(&a, &b).is_none());
+    }
+}
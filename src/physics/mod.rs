@@ -0,0 +1,3 @@
+pub mod collider;
+pub mod collision;
+pub mod rigid_body;
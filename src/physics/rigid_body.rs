@@ -0,0 +1,208 @@
+use bevy_ecs::{
+    component::Component,
+    event::{EventReader, EventWriter},
+    query::With,
+    system::{Query, Res},
+};
+use macroquad::prelude::*;
+
+use crate::{
+    combat::damage::DamageEvent,
+    physics::{collider::Sensor, collision::CollisionEvent},
+    time::time::Time,
+    transform::transform::Transform,
+};
+
+/// Restitution used for entities that collide but have no `RigidBody` of their own.
+const STATIC_RESTITUTION: f32 = 0.5;
+/// Relative speed along the contact normal below which impacts do no damage.
+const IMPACT_DAMAGE_THRESHOLD: f32 = 5.;
+const IMPACT_DAMAGE: f32 = 0.5;
+
+/// Linear and angular motion of an entity, shared by ships, asteroids and projectiles. Entities
+/// with a collider but no body are treated as immovable.
+#[derive(Component)]
+pub struct RigidBody {
+    pub velocity: Vec3,
+    /// Axis scaled by the rotation speed in radians per second.
+    pub angular_velocity: Vec3,
+    /// Force applied every step until changed, like engine thrust.
+    pub force: Vec3,
+    pub torque: Vec3,
+    mass: f32,
+    /// Moment of inertia, approximated as a solid sphere's.
+    inertia: f32,
+    pub restitution: f32,
+    pub linear_drag: f32,
+    pub angular_drag: f32,
+    /// How quickly the body is brought to `reference_velocity` while no force is applied.
+    pub stabilizer: Option<f32>,
+    /// Velocity the stabilizer settles on, like that of a ship being flown alongside.
+    pub reference_velocity: Vec3,
+    pub max_speed: Option<f32>,
+}
+
+impl RigidBody {
+    pub fn new(mass: f32, radius: f32) -> Self {
+        Self {
+            velocity: Vec3::ZERO,
+            angular_velocity: Vec3::ZERO,
+            force: Vec3::ZERO,
+            torque: Vec3::ZERO,
+            mass,
+            inertia: 0.4 * mass * radius * radius,
+            restitution: 0.5,
+            linear_drag: 0.,
+            angular_drag: 0.,
+            stabilizer: None,
+            reference_velocity: Vec3::ZERO,
+            max_speed: None,
+        }
+    }
+
+    pub fn with_velocity(mut self, velocity: Vec3) -> Self {
+        self.velocity = velocity;
+        self
+    }
+
+    pub fn with_drag(mut self, linear: f32, angular: f32) -> Self {
+        self.linear_drag = linear;
+        self.angular_drag = angular;
+        self
+    }
+
+    pub fn mass(&self) -> f32 {
+        self.mass
+    }
+
+    pub fn inverse_mass(&self) -> f32 {
+        1. / self.mass
+    }
+
+    pub fn inertia(&self) -> f32 {
+        self.inertia
+    }
+
+    pub fn inverse_inertia(&self) -> f32 {
+        1. / self.inertia
+    }
+
+    /// Velocity of a point on the body, given as an offset from its position.
+    pub fn point_velocity(&self, offset: Vec3) -> Vec3 {
+        self.velocity + self.angular_velocity.cross(offset)
+    }
+
+    /// Instantly changes the momentum of the body, spinning it if the impulse is applied away
+    /// from its centre.
+    pub fn apply_impulse(&mut self, impulse: Vec3, offset: Vec3) {
+        self.velocity += impulse * self.inverse_mass();
+        self.angular_velocity += offset.cross(impulse) * self.inverse_inertia();
+    }
+
+    /// Advances the body by one semi-implicit Euler step.
+    pub fn step(&mut self, transform: &mut Transform, dt: f32) {
+        self.velocity += self.force * self.inverse_mass() * dt;
+        self.angular_velocity += self.torque * self.inverse_inertia() * dt;
+
+        self.velocity /= 1. + self.linear_drag * dt;
+        self.angular_velocity /= 1. + self.angular_drag * dt;
+        if let Some(power) = self.stabilizer {
+            let t = (power * dt).clamp(0., 1.);
+            if self.force == Vec3::ZERO {
+                self.velocity = self.velocity.lerp(self.reference_velocity, t);
+            }
+            if self.torque == Vec3::ZERO {
+                self.angular_velocity = self.angular_velocity.lerp(Vec3::ZERO, t);
+            }
+        }
+        if let Some(max_speed) = self.max_speed {
+            self.velocity = self.velocity.clamp_length_max(max_speed);
+        }
+
+        transform.translate(self.velocity * dt);
+        let spin = Quat::from_scaled_axis(self.angular_velocity * dt);
+        transform.rotation = (spin * transform.rotation).normalize();
+    }
+}
+
+pub fn integrate_bodies(time: Res<Time>, mut query: Query<(&mut RigidBody, &mut Transform)>) {
+    for (mut body, mut transform) in query.iter_mut() {
+        body.step(&mut transform, time.fixed_delta());
+    }
+}
+
+/// Pushes overlapping entities apart and bounces their bodies off each other, damaging both by
+/// how hard they hit.
+pub fn resolve_collisions(
+    mut events: EventReader<CollisionEvent>,
+    mut bodies: Query<(&mut Transform, Option<&mut RigidBody>)>,
+    sensors: Query<(), With<Sensor>>,
+    mut damage: EventWriter<DamageEvent>,
+) {
+    for event in events.read() {
+        if sensors.contains(event.a) || sensors.contains(event.b) {
+            continue;
+        }
+        let Ok([(mut transform_a, mut body_a), (mut transform_b, mut body_b)]) =
+            bodies.get_many_mut([event.a, event.b])
+        else {
+            continue;
+        };
+
+        let inverse_mass = |body: &Option<_>| body.as_deref().map_or(0., RigidBody::inverse_mass);
+        let (inverse_a, inverse_b) = (inverse_mass(&body_a), inverse_mass(&body_b));
+        let total = inverse_a + inverse_b;
+        if total == 0. {
+            continue;
+        }
+
+        let correction = event.normal * event.depth / total;
+        transform_a.translate(-correction * inverse_a);
+        transform_b.translate(correction * inverse_b);
+
+        let offset_a = event.point - transform_a.position;
+        let offset_b = event.point - transform_b.position;
+        let velocity = |body: &Option<_>, offset| {
+            body.as_deref()
+                .map_or(Vec3::ZERO, |b: &RigidBody| b.point_velocity(offset))
+        };
+        let closing_speed =
+            (velocity(&body_b, offset_b) - velocity(&body_a, offset_a)).dot(event.normal);
+        if closing_speed >= 0. {
+            // Already separating
+            continue;
+        }
+
+        let angular = |body: &Option<_>, offset: Vec3| {
+            body.as_deref().map_or(0., |b: &RigidBody| {
+                (offset.cross(event.normal) * b.inverse_inertia())
+                    .cross(offset)
+                    .dot(event.normal)
+            })
+        };
+        let restitution = [&body_a, &body_b]
+            .iter()
+            .map(|body| {
+                body.as_deref()
+                    .map_or(STATIC_RESTITUTION, |b| b.restitution)
+            })
+            .fold(1., f32::min);
+        let impulse = -(1. + restitution) * closing_speed
+            / (total + angular(&body_a, offset_a) + angular(&body_b, offset_b))
+            * event.normal;
+
+        if let Some(body) = body_a.as_deref_mut() {
+            body.apply_impulse(-impulse, offset_a);
+        }
+        if let Some(body) = body_b.as_deref_mut() {
+            body.apply_impulse(impulse, offset_b);
+        }
+
+        let amount = (-closing_speed - IMPACT_DAMAGE_THRESHOLD).max(0.) * IMPACT_DAMAGE;
+        if amount > 0. {
+            for target in [event.a, event.b] {
+                damage.send(DamageEvent { target, amount });
+            }
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use macroquad::prelude::*;
+
+/// Pips shared between all systems, classic space sim style.
+const TOTAL_PIPS: u32 = 6;
+const MAX_PIPS: u32 = 4;
+const CAPACITY: f32 = 100.;
+/// Energy regenerated per second for every pip a system has.
+const REGEN_PER_PIP: f32 = 5.;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EnergySystem {
+    Engines,
+    Shields,
+    Weapons,
+}
+
+impl EnergySystem {
+    pub const ALL: [EnergySystem; 3] = [
+        EnergySystem::Engines,
+        EnergySystem::Shields,
+        EnergySystem::Weapons,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            EnergySystem::Engines => "ENG",
+            EnergySystem::Shields => "SYS",
+            EnergySystem::Weapons => "WEP",
+        }
+    }
+}
+
+/// A capacitor per system, each recharging according to how many pips it has been given.
+pub struct Energy {
+    pips: [u32; 3],
+    levels: [f32; 3],
+}
+
+impl Default for Energy {
+    fn default() -> Self {
+        Self {
+            pips: [TOTAL_PIPS / 3; 3],
+            levels: [CAPACITY; 3],
+        }
+    }
+}
+
+impl Energy {
+    pub fn pips(&self, system: EnergySystem) -> u32 {
+        self.pips[system as usize]
+    }
+
+    /// Charge of the system's capacitor from 0 to 1.
+    pub fn fraction(&self, system: EnergySystem) -> f32 {
+        self.levels[system as usize] / CAPACITY
+    }
+
+    /// Moves a pip to the system from whichever other system has the most.
+    pub fn add_pip(&mut self, system: EnergySystem) {
+        let to = system as usize;
+        if self.pips[to] >= MAX_PIPS {
+            return;
+        }
+        let from = (0..self.pips.len())
+            .filter(|i| *i != to)
+            .max_by_key(|i| self.pips[*i])
+            .unwrap();
+        if self.pips[from] > 0 {
+            self.pips[from] -= 1;
+            self.pips[to] += 1;
+        }
+    }
+
+    pub fn balance(&mut self) {
+        self.pips = Self::default().pips;
+    }
+
+    /// Output multiplier for a system, 1 at an even split and growing with every extra pip.
+    pub fn boost(&self, system: EnergySystem) -> f32 {
+        0.5 + self.pips(system) as f32 / 4.
+    }
+
+    pub fn regenerate(&mut self, dt: f32) {
+        for (level, pips) in self.levels.iter_mut().zip(self.pips) {
+            *level = (*level + pips as f32 * REGEN_PER_PIP * dt).min(CAPACITY);
+        }
+    }
+
+    /// Takes energy from a system's capacitor, failing without taking anything if there isn't
+    /// enough.
+    pub fn drain(&mut self, system: EnergySystem, amount: f32) -> bool {
+        let level = &mut self.levels[system as usize];
+        if *level < amount {
+            return false;
+        }
+        *level -= amount;
+        true
+    }
+
+    pub fn draw(&self, x: f32, y: f32) {
+        for (i, system) in EnergySystem::ALL.into_iter().enumerate() {
+            let x = x + i as f32 * 60.;
+            draw_text(system.name(), x, y, 20.0, WHITE);
+            for pip in 0..MAX_PIPS {
+                let color = if pip < self.pips(system) {
+                    SKYBLUE
+                } else {
+                    DARKGRAY
+                };
+                draw_rectangle(x, y - 30. - pip as f32 * 12., 40., 8., color);
+            }
+            draw_rectangle(x, y + 6., 40., 6., DARKGRAY);
+            draw_rectangle(x, y + 6., 40. * self.fraction(system), 6., YELLOW);
+        }
+    }
+}
@@ -1 +1,2 @@
+pub mod energy;
 pub mod player;
@@ -1,106 +1,366 @@
 use bevy_ecs::{
     bundle::Bundle,
     component::Component,
+    entity::Entity,
+    event::EventWriter,
+    query::{With, Without},
     system::{Query, Res},
 };
 use macroquad::prelude::*;
 
 use crate::{
-    camera::camera::CameraState, transform::transform::Transform, utils::mesh_utils::Model,
+    camera::camera::{CameraShake, CameraState},
+    combat::{
+        damage::{Health, Shield},
+        destruction::{Respawning, SpawnPoint},
+    },
+    input::input_map::{Action, Actions, InputMap},
+    physics::{collider::Collider, rigid_body::RigidBody},
+    player::energy::{Energy, EnergySystem},
+    projectiles::projectile::Armament,
+    time::time::Time,
+    transform::transform::Transform,
+    utils::mesh_utils::Model,
 };
 
 const ACCELERATION: f32 = 200.0;
+/// Pitch, yaw and roll rates in radians per second at full deflection.
+const TURN_RATE: Vec3 = Vec3::new(1.5, 1.5, 2.5);
+const TURN_RESPONSE: f32 = 8.0;
+/// Angular acceleration at full deflection with flight assist off.
+const ANGULAR_ACCELERATION: f32 = 3.0;
+/// Full deflection at this many pixels per second of mouse movement.
+const MOUSE_STEER: f32 = 1.0 / 400.0;
+/// Max speed and forward thrust multipliers while the afterburner is lit.
+const AFTERBURNER_SPEED: f32 = 1.75;
+const AFTERBURNER_THRUST: f32 = 2.0;
+/// Engine energy burnt per second by the afterburner.
+const AFTERBURNER_DRAIN: f32 = 25.0;
+/// Engine charge needed to light the afterburner, so a burnt out one doesn't sputter back on
+/// every time a little energy trickles in.
+const AFTERBURNER_IGNITION: f32 = 0.25;
+/// Camera trauma from engaging the afterburner.
+const AFTERBURNER_TRAUMA: f32 = 0.3;
 
 #[derive(Component)]
 pub struct Player {
-    velocity: Vec3,
-    max_speed: f32,
     color: Color,
-    stabilizing: bool,
-    stabilizer_power: f32,
+    max_speed: f32,
+    pub energy: Energy,
+    afterburning: bool,
+    /// Whether the afterburner was asked for last step, so only a fresh press shakes the camera.
+    afterburner_held: bool,
 }
 
 #[derive(Bundle)]
 pub struct PlayerBundle {
     pub player: Player,
+    pub armament: Armament,
     pub model: Model,
+    pub collider: Collider,
+    pub body: RigidBody,
+    pub controls: FlightControls,
     pub transform: Transform,
+    pub health: Health,
+    pub shield: Shield,
+    pub spawn: SpawnPoint,
 }
 
 impl Player {
     pub fn new(color: Color, max_speed: f32) -> Self {
         Self {
-            velocity: vec3(0., 0., 0.),
-            max_speed,
             color,
-            stabilizing: false,
-            stabilizer_power: 2.,
+            max_speed,
+            energy: Energy::default(),
+            afterburning: false,
+            afterburner_held: false,
         }
     }
 
-    pub fn update(&mut self, transform: &mut Transform, dt: f32) {
-        let last_pos = transform.position;
-        transform.position += self.velocity * dt;
-        if self.stabilizing {
-            self.stabilize(dt);
-        } else {
-            self.stabilizing = true;
-        }
+    pub fn max_speed(&self) -> f32 {
+        self.max_speed
+    }
 
-        transform.translate(transform.position - last_pos);
+    pub fn afterburning(&self) -> bool {
+        self.afterburning
     }
+}
+
+/// What the pilot is asking the ship to do, sampled once a frame and flown in fixed steps.
+#[derive(Component)]
+pub struct FlightControls {
+    /// Thrust along the ship's right, up and forward axes, each from -1 to 1.
+    pub thrust: Vec3,
+    /// Pitch, yaw and roll, each from -1 to 1.
+    pub rotation: Vec3,
+    pub flight_assist: FlightAssist,
+    pub assist_power: f32,
+    /// Entity whose velocity is matched in `FlightAssist::VelocityMatch`.
+    pub target: Option<Entity>,
+    pub afterburner: bool,
+}
 
-    pub fn stabilize(&mut self, dt: f32) {
-        self.velocity = self
-            .velocity
-            .lerp(Vec3::ZERO, (self.stabilizer_power * dt).clamp(0., 1.));
+impl FlightControls {
+    pub fn new(assist_power: f32) -> Self {
+        Self {
+            thrust: Vec3::ZERO,
+            rotation: Vec3::ZERO,
+            flight_assist: FlightAssist::Coupled,
+            assist_power,
+            target: None,
+            afterburner: false,
+        }
     }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FlightAssist {
+    /// No assistance at all, the ship keeps drifting and spinning until countered.
+    Newtonian,
+    /// Holds the ship's attitude and bleeds off its velocity whenever the controls are released.
+    Coupled,
+    /// Like `Coupled`, but settles on the target's velocity instead of coming to a stop.
+    VelocityMatch,
+}
 
-    pub fn add_force(&mut self, dir: Vec3, amount: f32) {
-        self.velocity += dir * amount;
+impl FlightAssist {
+    pub fn next(self) -> Self {
+        match self {
+            FlightAssist::Newtonian => FlightAssist::Coupled,
+            FlightAssist::Coupled => FlightAssist::VelocityMatch,
+            FlightAssist::VelocityMatch => FlightAssist::Newtonian,
+        }
+    }
 
-        self.velocity = self.velocity.clamp_length(0., self.max_speed);
-        if dir != Vec3::ZERO {
-            self.stabilizing = false;
+    pub fn name(self) -> &'static str {
+        match self {
+            FlightAssist::Newtonian => "Newtonian",
+            FlightAssist::Coupled => "Coupled",
+            FlightAssist::VelocityMatch => "Velocity match",
         }
     }
 }
 
-pub fn player_input(mut query: Query<&mut Player>, camera: Res<CameraState>) {
-    let delta = get_frame_time();
-    let mut player = query.single_mut();
-    if is_key_down(KeyCode::Up) || is_key_down(KeyCode::W) {
-        player.add_force(camera.front, delta * ACCELERATION);
+type Targetable = (With<Health>, Without<Player>);
+
+pub fn player_input(
+    mut query: Query<(&mut Player, &mut FlightControls, &Transform)>,
+    targets: Query<(Entity, &Transform), Targetable>,
+    actions: Res<Actions>,
+    camera: Res<CameraState>,
+    time: Res<Time>,
+) {
+    let (mut player, mut controls, transform) = query.single_mut();
+    if !camera.mode.controls_ship() {
+        // Let go of the controls while the keys fly the camera instead
+        controls.thrust = Vec3::ZERO;
+        controls.rotation = Vec3::ZERO;
+        controls.afterburner = false;
+        return;
     }
-    if is_key_down(KeyCode::Down) || is_key_down(KeyCode::S) {
-        player.add_force(-camera.front, delta * ACCELERATION);
+
+    controls.thrust = vec3(
+        actions.axis(Action::StrafeLeft, Action::StrafeRight),
+        actions.axis(Action::StrafeDown, Action::StrafeUp),
+        actions.axis(Action::ThrustBackward, Action::ThrustForward)
+            + actions.value(Action::Throttle),
+    )
+    .clamp(-Vec3::ONE, Vec3::ONE);
+
+    // Mouse speed in pixels per second steers, so the turn rate doesn't depend on frame rate
+    let steer = if time.delta() > 0. && !camera.mode.uses_mouse() {
+        vec2(actions.motion(Action::Pitch), actions.motion(Action::Yaw)) / time.delta()
+            * MOUSE_STEER
+    } else {
+        Vec2::ZERO
+    };
+    controls.rotation = vec3(
+        steer.x + actions.value(Action::Pitch),
+        steer.y + actions.value(Action::Yaw),
+        actions.axis(Action::RollLeft, Action::RollRight) + actions.value(Action::Roll),
+    )
+    .clamp(-Vec3::ONE, Vec3::ONE);
+
+    controls.afterburner = actions.down(Action::Afterburner);
+
+    for (action, system) in [
+        Action::PipsEngines,
+        Action::PipsShields,
+        Action::PipsWeapons,
+    ]
+    .into_iter()
+    .zip(EnergySystem::ALL)
+    {
+        if actions.pressed(action) {
+            player.energy.add_pip(system);
+        }
     }
-    if is_key_down(KeyCode::Left) || is_key_down(KeyCode::A) {
-        player.add_force(-camera.right, delta * ACCELERATION);
+    if actions.pressed(Action::BalancePips) {
+        player.energy.balance();
+    }
+
+    if actions.pressed(Action::FlightAssist) {
+        controls.flight_assist = controls.flight_assist.next();
     }
-    if is_key_down(KeyCode::Right) || is_key_down(KeyCode::D) {
-        player.add_force(camera.right, delta * ACCELERATION);
+    if actions.pressed(Action::Target) {
+        controls.target = targets
+            .iter()
+            .min_by(|(_, a), (_, b)| {
+                let distance = |t: &Transform| t.position.distance_squared(transform.position);
+                distance(a).total_cmp(&distance(b))
+            })
+            .map(|(entity, _)| entity);
     }
-    if is_key_down(KeyCode::Space) {
-        player.add_force(camera.up, delta * ACCELERATION);
+}
+
+/// Turns the flight controls into thrust and torque along the ship's own axes.
+pub fn fly_ship(
+    mut query: Query<
+        (&mut Player, &FlightControls, &mut RigidBody, &Transform),
+        Without<Respawning>,
+    >,
+    bodies: Query<&RigidBody, Without<Player>>,
+    mut shakes: EventWriter<CameraShake>,
+    time: Res<Time>,
+) {
+    // Nothing to fly while waiting to respawn
+    let Ok((mut player, controls, mut body, transform)) = query.get_single_mut() else {
+        return;
+    };
+    let axes = [transform.right(), transform.up(), transform.forward()];
+    let local = |v: Vec3| axes[0] * v.x + axes[1] * v.y + axes[2] * v.z;
+
+    let was_afterburning = player.afterburning;
+    let can_burn =
+        was_afterburning || player.energy.fraction(EnergySystem::Engines) >= AFTERBURNER_IGNITION;
+    player.afterburning = controls.afterburner
+        && can_burn
+        && player.energy.drain(
+            EnergySystem::Engines,
+            AFTERBURNER_DRAIN * time.fixed_delta(),
+        );
+    let engaged = player.afterburning && !player.afterburner_held;
+    player.afterburner_held = controls.afterburner;
+    if engaged {
+        shakes.send(CameraShake {
+            trauma: AFTERBURNER_TRAUMA,
+        });
     }
-    if is_key_down(KeyCode::LeftControl) {
-        player.add_force(-camera.up, delta * ACCELERATION);
+    let mut thrust = controls.thrust * player.energy.boost(EnergySystem::Engines);
+    if player.afterburning {
+        thrust.z = AFTERBURNER_THRUST;
     }
+    body.force = local(thrust) * ACCELERATION * body.mass();
+    body.max_speed = Some(if player.afterburning {
+        player.max_speed * AFTERBURNER_SPEED
+    } else {
+        player.max_speed
+    });
+
+    let rotation = local(controls.rotation * TURN_RATE);
+    body.torque = if controls.flight_assist == FlightAssist::Newtonian {
+        local(controls.rotation) * ANGULAR_ACCELERATION * body.inertia()
+    } else {
+        // Drive the angular velocity towards the requested rate, stopping the spin when released
+        (rotation - body.angular_velocity) * TURN_RESPONSE * body.inertia()
+    };
+
+    body.stabilizer =
+        (controls.flight_assist != FlightAssist::Newtonian).then_some(controls.assist_power);
+    body.reference_velocity = match controls.flight_assist {
+        FlightAssist::VelocityMatch => controls
+            .target
+            .and_then(|target| bodies.get(target).ok())
+            .map_or(Vec3::ZERO, |target| target.velocity),
+        _ => Vec3::ZERO,
+    };
 }
 
-pub fn update_player(mut query: Query<(&mut Player, &mut Transform)>, camera: Res<CameraState>) {
-    let (mut player, mut transform) = query.single_mut();
-    let mut q: Quat = Quat::IDENTITY;
-    let rot = Vec3::from(Quat::to_euler(transform.rotation, EulerRot::XYZ));
-    let a: Vec3 = Vec3::cross(rot, camera.front);
-    q.x = a.x;
-    q.y = a.y;
-    q.z = a.z;
-    q.w =
-        f32::sqrt((rot.length().powf(2.)) * (rot.length().powf(2.))) + Vec3::dot(rot, camera.front);
-    let rot = Vec3::from(q.to_euler(EulerRot::XYZ));
-    transform.rotate(rot.length() * 10. * get_frame_time(), rot);
-
-    player.update(&mut transform, get_frame_time());
+pub fn regenerate_energy(mut query: Query<&mut Player>, time: Res<Time>) {
+    for mut player in query.iter_mut() {
+        player.energy.regenerate(time.fixed_delta());
+    }
+}
+
+type PlayerStatus<'a> = (
+    &'a Player,
+    &'a FlightControls,
+    &'a Transform,
+    &'a Health,
+    &'a Shield,
+    Option<&'a Respawning>,
+);
+
+pub fn draw_player_hud(
+    query: Query<PlayerStatus>,
+    targets: Query<&Transform, Without<Player>>,
+    input_map: Res<InputMap>,
+) {
+    for (player, controls, transform, health, shield, respawning) in query.iter() {
+        draw_text(
+            format!(
+                "Hull: {:.0}  Shield: {:.0}",
+                health.current(),
+                shield.current()
+            )
+            .as_str(),
+            10.0,
+            48.0 + 48.0 + 48.0,
+            30.0,
+            WHITE,
+        );
+        draw_text(
+            format!(
+                "Flight assist <{}>: {}",
+                input_map.label(Action::FlightAssist),
+                controls.flight_assist.name()
+            )
+            .as_str(),
+            10.0,
+            48.0 + 48.0 + 72.0,
+            30.0,
+            WHITE,
+        );
+        let target = controls
+            .target
+            .and_then(|target| targets.get(target).ok())
+            .map_or("none".to_string(), |target| {
+                format!("{:.0}m", target.position.distance(transform.position))
+            });
+        draw_text(
+            format!("Target <{}>: {}", input_map.label(Action::Target), target).as_str(),
+            10.0,
+            48.0 + 48.0 + 96.0,
+            30.0,
+            WHITE,
+        );
+
+        player
+            .energy
+            .draw(screen_width() - 190., screen_height() - 30.);
+        if player.afterburning {
+            draw_text(
+                "AFTERBURNER",
+                screen_width() - 190.,
+                screen_height() - 100.,
+                30.0,
+                ORANGE,
+            );
+        }
+
+        if let Some(respawning) = respawning {
+            let text = format!(
+                "DESTROYED - respawning in {:.0}",
+                respawning.remaining().ceil()
+            );
+            let size = measure_text(&text, None, 40, 1.0);
+            draw_text(
+                &text,
+                (screen_width() - size.width) / 2.,
+                screen_height() / 2.,
+                40.0,
+                RED,
+            );
+        }
+    }
 }
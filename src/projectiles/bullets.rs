@@ -1,32 +1,99 @@
+use bevy_ecs::{
+    bundle::Bundle,
+    component::Component,
+    entity::Entity,
+    query::Without,
+    system::{Commands, Query, Res},
+};
 use macroquad::prelude::*;
-use glam::vec3;
 
-pub struct Bullet
-{
-    size:Vec3,
+use crate::{
+    camera::camera::CameraState,
+    physics::{
+        collider::{Collider, Sensor},
+        rigid_body::RigidBody,
+    },
+    renderer::renderer::MeshPipeline,
+    time::time::Time,
+    transform::transform::{PreviousTransform, Transform},
+    utils::mesh_utils::Model,
+};
+
+#[derive(Component)]
+pub struct Bullet {
+    size: Vec3,
     color: Color,
-    velocity: Vec3,
-    pos: Vec3,
     origin: Vec3,
+    range: f32,
+    damage: f32,
 }
 
 impl Bullet {
-    pub fn new(size:Vec3, color:Color, velocity:Vec3, pos:Vec3, origin: Vec3)->Self{
+    pub fn new(size: Vec3, color: Color, origin: Vec3, range: f32, damage: f32) -> Self {
         Self {
             size,
             color,
-            velocity,
-            pos,
             origin,
+            range,
+            damage,
         }
     }
 
-    pub fn draw_m(&self)
-    {
-        draw_cube(self.pos, self.size, None, self.color);
+    pub fn damage(&self) -> f32 {
+        self.damage
     }
+}
+
+/// Seconds left before the entity is despawned.
+#[derive(Component)]
+pub struct Lifetime(pub f32);
+
+/// Entity that fired a projectile, so it can be ignored when checking hits.
+#[derive(Component)]
+pub struct Owner(pub Entity);
+
+#[derive(Bundle)]
+pub struct BulletBundle {
+    pub bullet: Bullet,
+    pub transform: Transform,
+    pub body: RigidBody,
+    pub lifetime: Lifetime,
+    pub owner: Owner,
+    /// Unit sphere scaled to the bullet by its `Transform`.
+    pub collider: Collider,
+    pub sensor: Sensor,
+}
+
+/// Despawns bullets that ran out of time or range, they are moved by their `RigidBody`.
+pub fn update_bullets(
+    mut commands: Commands,
+    mut query: Query<(Entity, &Bullet, &mut Lifetime, &Transform)>,
+    time: Res<Time>,
+) {
+    let delta = time.fixed_delta();
+    for (entity, bullet, mut lifetime, transform) in query.iter_mut() {
+        lifetime.0 -= delta;
+
+        if lifetime.0 <= 0. || transform.position.distance(bullet.origin) > bullet.range {
+            commands.entity(entity).despawn();
+        }
+    }
+}
 
-    pub fn update(&mut self, dt:f32){
-        self.pos += self.velocity * vec3(1.0, 1.0, 1.0) * dt;
+/// Draws bullets without a model as plain cubes, the rest are drawn by `draw_models`.
+pub fn draw_bullets(
+    query: Query<(&Bullet, &Transform, Option<&PreviousTransform>), Without<Model>>,
+    mesh_pipeline: Res<MeshPipeline>,
+    camera: Res<CameraState>,
+    time: Res<Time>,
+) {
+    for (bullet, transform, previous) in query.iter() {
+        let position = transform.interpolated(previous, time.alpha()).position;
+        mesh_pipeline.draw_cube(
+            position,
+            bullet.size,
+            bullet.color,
+            camera.depth_coefficient(),
+        );
     }
 }
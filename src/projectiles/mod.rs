@@ -1,2 +1,2 @@
-mod bullet;
-mod projectile;
+pub mod bullets;
+pub mod projectile;
@@ -1,9 +1,413 @@
-pub enum sDirection {
-    x,
-    y,
-    z,
+use bevy_ecs::{
+    component::Component,
+    entity::Entity,
+    query::{With, Without},
+    system::{Commands, Query, Res},
+};
+use macroquad::prelude::*;
+
+use crate::{
+    camera::camera::CameraState,
+    combat::{damage::Health, destruction::Respawning},
+    input::input_map::{Action, Actions},
+    physics::{
+        collider::{Collider, Sensor, Shape},
+        rigid_body::RigidBody,
+    },
+    player::{energy::EnergySystem, player::Player},
+    projectiles::bullets::{Bullet, BulletBundle, Lifetime, Owner},
+    time::time::Time,
+    transform::transform::Transform,
+    utils::{asset_utils::AssetServer, mesh_utils::Model},
+};
+
+/// What limits how often a weapon can be fired beyond its fire interval.
+#[derive(Clone, Copy)]
+pub enum Budget {
+    Ammo {
+        capacity: u32,
+    },
+    Heat {
+        capacity: f32,
+        per_shot: f32,
+        cooling: f32,
+    },
+}
+
+#[derive(Clone, Copy)]
+pub struct ProjectileDef {
+    pub speed: f32,
+    pub lifetime: f32,
+    pub range: f32,
+    pub size: f32,
+    pub color: Color,
+    /// Model drawn instead of a plain cube, loaded from res/.
+    pub model: Option<&'static str>,
+    /// Turn rate in radians per second towards the closest target, zero for dumb projectiles.
+    pub homing: f32,
+    pub damage: f32,
+}
+
+#[derive(Clone, Copy)]
+pub struct WeaponDef {
+    pub name: &'static str,
+    pub fire_interval: f32,
+    pub budget: Budget,
+    pub projectiles_per_shot: u32,
+    /// Half angle in radians of the cone projectiles are randomly spread in.
+    pub spread: f32,
+    /// Drawn from the weapons capacitor on every shot.
+    pub energy: f32,
+    pub projectile: ProjectileDef,
+}
+
+pub const WEAPONS: [WeaponDef; 4] = [
+    WeaponDef {
+        name: "Blaster",
+        fire_interval: 0.15,
+        budget: Budget::Heat {
+            capacity: 100.,
+            per_shot: 8.,
+            cooling: 30.,
+        },
+        projectiles_per_shot: 1,
+        spread: 0.01,
+        energy: 2.,
+        projectile: ProjectileDef {
+            speed: 400.,
+            lifetime: 3.,
+            range: 1000.,
+            size: 0.5,
+            color: YELLOW,
+            model: None,
+            homing: 0.,
+            damage: 5.,
+        },
+    },
+    WeaponDef {
+        name: "Spread Shot",
+        fire_interval: 0.6,
+        budget: Budget::Ammo { capacity: 40 },
+        projectiles_per_shot: 6,
+        spread: 0.12,
+        energy: 5.,
+        projectile: ProjectileDef {
+            speed: 300.,
+            lifetime: 1.5,
+            range: 400.,
+            size: 0.4,
+            color: ORANGE,
+            model: None,
+            homing: 0.,
+            damage: 4.,
+        },
+    },
+    WeaponDef {
+        name: "Railgun",
+        fire_interval: 1.2,
+        budget: Budget::Heat {
+            capacity: 100.,
+            per_shot: 45.,
+            cooling: 20.,
+        },
+        projectiles_per_shot: 1,
+        spread: 0.,
+        energy: 25.,
+        projectile: ProjectileDef {
+            speed: 2000.,
+            lifetime: 1.,
+            range: 2000.,
+            size: 0.3,
+            color: SKYBLUE,
+            model: None,
+            homing: 0.,
+            damage: 40.,
+        },
+    },
+    WeaponDef {
+        name: "Homing Missile",
+        fire_interval: 0.8,
+        budget: Budget::Ammo { capacity: 8 },
+        projectiles_per_shot: 1,
+        spread: 0.,
+        energy: 0.,
+        projectile: ProjectileDef {
+            speed: 150.,
+            lifetime: 6.,
+            range: 1500.,
+            size: 0.5,
+            color: RED,
+            model: Some("Ship.obj"),
+            homing: 2.,
+            damage: 30.,
+        },
+    },
+];
+
+/// Runtime state of one weapon in an `Armament`.
+pub struct Weapon {
+    pub def: WeaponDef,
+    model: Option<Model>,
+    cooldown: f32,
+    ammo: u32,
+    heat: f32,
+}
+
+impl Weapon {
+    pub async fn new(def: WeaponDef, assets: &mut AssetServer) -> Self {
+        let model = match def.projectile.model {
+            Some(file_name) => Some(Model::new(file_name, "res/", assets).await),
+            None => None,
+        };
+        let ammo = match def.budget {
+            Budget::Ammo { capacity } => capacity,
+            Budget::Heat { .. } => 0,
+        };
+
+        Self {
+            def,
+            model,
+            cooldown: 0.,
+            ammo,
+            heat: 0.,
+        }
+    }
+
+    pub fn can_fire(&self) -> bool {
+        self.cooldown == 0.
+            && match self.def.budget {
+                Budget::Ammo { .. } => self.ammo > 0,
+                Budget::Heat {
+                    capacity, per_shot, ..
+                } => self.heat + per_shot <= capacity,
+            }
+    }
+
+    fn update(&mut self, dt: f32) {
+        self.cooldown = (self.cooldown - dt).max(0.);
+        if let Budget::Heat { cooling, .. } = self.def.budget {
+            self.heat = (self.heat - cooling * dt).max(0.);
+        }
+    }
+
+    fn consume(&mut self) {
+        self.cooldown = self.def.fire_interval;
+        match self.def.budget {
+            Budget::Ammo { .. } => self.ammo -= 1,
+            Budget::Heat { per_shot, .. } => self.heat += per_shot,
+        }
+    }
+
+    /// Model of the projectiles, for swapping in a reloaded version.
+    pub fn model_mut(&mut self) -> Option<&mut Model> {
+        self.model.as_mut()
+    }
+
+    /// Remaining ammo or current heat, for the HUD.
+    pub fn status(&self) -> String {
+        match self.def.budget {
+            Budget::Ammo { capacity } => format!("Ammo {}/{}", self.ammo, capacity),
+            Budget::Heat { capacity, .. } => format!("Heat {:.0}%", self.heat / capacity * 100.),
+        }
+    }
 }
 
-pub struct sBullet;
+/// Weapons carried by a ship and the hardpoints they fire from, in model space.
+#[derive(Component)]
+pub struct Armament {
+    pub weapons: Vec<Weapon>,
+    pub selected: usize,
+    hardpoints: Vec<Vec3>,
+    next_hardpoint: usize,
+}
+
+impl Armament {
+    pub async fn new(hardpoints: Vec<Vec3>, assets: &mut AssetServer) -> Self {
+        let mut weapons = Vec::new();
+        for def in WEAPONS {
+            weapons.push(Weapon::new(def, assets).await);
+        }
+
+        Self {
+            weapons,
+            selected: 0,
+            hardpoints,
+            next_hardpoint: 0,
+        }
+    }
 
+    pub fn selected_weapon(&self) -> &Weapon {
+        &self.weapons[self.selected]
+    }
 
+    /// Model space position of the hardpoint to fire from next, alternating between them.
+    fn take_hardpoint(&mut self) -> Vec3 {
+        if self.hardpoints.is_empty() {
+            return Vec3::ZERO;
+        }
+        let hardpoint = self.hardpoints[self.next_hardpoint % self.hardpoints.len()];
+        self.next_hardpoint = (self.next_hardpoint + 1) % self.hardpoints.len();
+        hardpoint
+    }
+}
+
+/// Steers a projectile towards the closest target in front of it.
+#[derive(Component)]
+pub struct Homing {
+    turn_rate: f32,
+}
+
+/// Light enough that projectiles barely push what they hit.
+const PROJECTILE_MASS: f32 = 0.1;
+const WEAPON_ACTIONS: [Action; 4] = [
+    Action::Weapon1,
+    Action::Weapon2,
+    Action::Weapon3,
+    Action::Weapon4,
+];
+
+pub fn select_weapon(mut query: Query<&mut Armament, With<Player>>, actions: Res<Actions>) {
+    for mut armament in query.iter_mut() {
+        for (i, action) in WEAPON_ACTIONS.iter().enumerate() {
+            if actions.pressed(*action) && i < armament.weapons.len() {
+                armament.selected = i;
+            }
+        }
+    }
+}
+
+pub fn fire_weapons(
+    mut commands: Commands,
+    mut query: Query<
+        (Entity, &mut Armament, &mut Player, &RigidBody, &Transform),
+        Without<Respawning>,
+    >,
+    camera: Res<CameraState>,
+    actions: Res<Actions>,
+    time: Res<Time>,
+) {
+    let delta = time.fixed_delta();
+    let firing = camera.mode.controls_ship() && actions.down(Action::Fire);
+    for (entity, mut armament, mut player, body, transform) in query.iter_mut() {
+        for weapon in armament.weapons.iter_mut() {
+            weapon.update(delta);
+        }
+
+        let selected = armament.selected;
+        if !firing
+            || !armament.weapons[selected].can_fire()
+            || !player
+                .energy
+                .drain(EnergySystem::Weapons, armament.weapons[selected].def.energy)
+        {
+            continue;
+        }
+        armament.weapons[selected].consume();
+
+        let origin = transform
+            .model_matrix()
+            .transform_point3(armament.take_hardpoint());
+        let weapon = &armament.weapons[selected];
+        let projectile = weapon.def.projectile;
+        for _ in 0..weapon.def.projectiles_per_shot {
+            let direction = spread_direction(transform, weapon.def.spread);
+            let mut bullet = commands.spawn(BulletBundle {
+                bullet: Bullet::new(
+                    Vec3::ONE * projectile.size,
+                    projectile.color,
+                    origin,
+                    projectile.range,
+                    projectile.damage,
+                ),
+                transform: Transform {
+                    position: origin,
+                    scale: Vec3::ONE * projectile.size,
+                    rotation: Quat::from_rotation_arc(Vec3::Z, direction),
+                },
+                body: RigidBody::new(PROJECTILE_MASS, projectile.size)
+                    .with_velocity(body.velocity + direction * projectile.speed),
+                lifetime: Lifetime(projectile.lifetime),
+                owner: Owner(entity),
+                collider: Collider {
+                    shape: Shape::Sphere {
+                        center: Vec3::ZERO,
+                        radius: 1.,
+                    },
+                },
+                sensor: Sensor,
+            });
+            if let Some(model) = &weapon.model {
+                bullet.insert(model.clone());
+            }
+            if projectile.homing > 0. {
+                bullet.insert(Homing {
+                    turn_rate: projectile.homing,
+                });
+            }
+        }
+    }
+}
+
+/// Random direction within a cone of the given half angle around the transform's forward axis.
+fn spread_direction(transform: &Transform, spread: f32) -> Vec3 {
+    let forward = transform.forward();
+    if spread <= 0. {
+        return forward;
+    }
+
+    let angle = rand::gen_range(0., std::f32::consts::TAU);
+    let offset = spread * rand::gen_range(0f32, 1.).sqrt();
+    let sideways = transform.rotation * vec3(angle.cos(), angle.sin(), 0.);
+    (forward + sideways * offset.tan()).normalize()
+}
+
+type Target = (With<Health>, Without<Bullet>, Without<Respawning>);
+type Missile<'a> = (&'a Homing, &'a Owner, &'a mut Transform, &'a mut RigidBody);
+
+pub fn steer_homing(
+    mut missiles: Query<Missile, With<Bullet>>,
+    targets: Query<(Entity, &Transform), Target>,
+    time: Res<Time>,
+) {
+    let delta = time.fixed_delta();
+    for (homing, owner, mut transform, mut body) in missiles.iter_mut() {
+        let heading = body.velocity.normalize_or_zero();
+        let target = targets
+            .iter()
+            .filter(|(entity, _)| *entity != owner.0)
+            .map(|(_, target)| target.position - transform.position)
+            .filter(|to_target| to_target.dot(heading) > 0.)
+            .min_by(|a, b| a.length_squared().total_cmp(&b.length_squared()));
+        let Some(to_target) = target else {
+            continue;
+        };
+
+        let desired = to_target.normalize_or_zero();
+        let angle = heading.angle_between(desired);
+        if angle > 0. {
+            let turn = (homing.turn_rate * delta / angle).min(1.);
+            let new_heading = heading.lerp(desired, turn).normalize_or_zero();
+            body.velocity = new_heading * body.velocity.length();
+            transform.rotation = Quat::from_rotation_arc(Vec3::Z, new_heading);
+        }
+    }
+}
+
+pub fn draw_armament_hud(query: Query<&Armament, With<Player>>) {
+    for armament in query.iter() {
+        let weapon = armament.selected_weapon();
+        draw_text(
+            format!(
+                "[{}] {} - {}",
+                armament.selected + 1,
+                weapon.def.name,
+                weapon.status()
+            )
+            .as_str(),
+            10.0,
+            48.0 + 48.0,
+            30.0,
+            WHITE,
+        );
+    }
+}
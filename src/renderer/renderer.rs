@@ -1,133 +1,292 @@
-mod raw_miniquad {
-    use macroquad::{color::Color, miniquad};
-    use miniquad::*;
-
-    #[repr(C)]
-    struct Vec2 {
-        x: f32,
-        y: f32,
-    }
+use bevy_ecs::system::Resource;
+use bytemuck::{Pod, Zeroable};
+use macroquad::{
+    color::{Color, WHITE},
+    math::{vec2, vec3, Mat4, Quat, Vec3},
+    miniquad::*,
+    models::{Mesh, Vertex},
+    window::get_internal_gl,
+};
 
-    #[repr(C)]
-    struct Vec3 {
-        x: f32,
-        y: f32,
-        z: f32,
-    }
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct GpuVertex {
+    position: [f32; 3],
+    uv: [f32; 2],
+    color: [f32; 4],
+    normal: [f32; 3],
+}
+
+#[repr(C)]
+struct MeshUniforms {
+    model: Mat4,
+    projection: Mat4,
+    light_color: Vec3,
+    object_color: Vec3,
+    model_pos: Vec3,
+    depth_coefficient: f32,
+}
+
+/// Vertex and index buffers for a single mesh, uploaded to the GPU once on creation.
+pub struct GpuMesh {
+    vertex_buffer: BufferId,
+    index_buffer: BufferId,
+    index_count: i32,
+    texture: Option<TextureId>,
+}
+
+impl GpuMesh {
+    pub fn new(mesh: &Mesh) -> Self {
+        let vertices = mesh
+            .vertices
+            .iter()
+            .map(|v| GpuVertex {
+                position: v.position.into(),
+                uv: v.uv.into(),
+                color: [v.color.r, v.color.g, v.color.b, v.color.a],
+                normal: v.normal.into(),
+            })
+            .collect::<Vec<_>>();
+
+        let ctx = unsafe { get_internal_gl() }.quad_context;
+        let vertex_buffer = ctx.new_buffer(
+            BufferType::VertexBuffer,
+            BufferUsage::Immutable,
+            BufferSource::slice(&vertices),
+        );
+        let index_buffer = ctx.new_buffer(
+            BufferType::IndexBuffer,
+            BufferUsage::Immutable,
+            BufferSource::slice(&mesh.indices),
+        );
 
-    #[repr(C)]
-    struct Vertex {
-        pos: Vec3,
-        normal: Vec3,
-        uv: Vec2,
-        color: Color,
+        Self {
+            vertex_buffer,
+            index_buffer,
+            index_count: mesh.indices.len() as i32,
+            texture: mesh.texture.as_ref().map(|t| t.raw_miniquad_id()),
+        }
     }
+}
 
-    pub struct Stage {
-        pub pipeline: Pipeline,
-        pub bindings: Bindings,
+impl Drop for GpuMesh {
+    fn drop(&mut self) {
+        let ctx = unsafe { get_internal_gl() }.quad_context;
+        ctx.delete_buffer(self.vertex_buffer);
+        ctx.delete_buffer(self.index_buffer);
     }
+}
 
-    impl Stage {
-        pub fn new(ctx: &mut dyn RenderingBackend) -> Stage {
-            #[rustfmt::skip]
-            let vertices: [Vertex; 4] = [
-                Vertex { pos : Vec3 { x: -0.5, y: -0.5, z: -0.5 }, normal: Vec3 { x: -0.5, y: -0.5, z: -0.5 }, uv: Vec2 { x: 0., y: 0. }, color: Color::from_rgba(100, 200, 30, 255) },
-                Vertex { pos : Vec3 { x:  0.5, y: -0.5, z: -0.5 }, normal: Vec3 { x:  0.5, y: -0.5, z: -0.5 }, uv: Vec2 { x: 1., y: 0. }, color: Color::from_rgba(100, 200, 30, 255) },
-                Vertex { pos : Vec3 { x:  0.5, y:  0.5, z:  0.5 }, normal: Vec3 { x:  0.5, y:  0.5, z:  0.5 }, uv: Vec2 { x: 1., y: 1. }, color: Color::from_rgba(100, 200, 30, 255) },
-                Vertex { pos : Vec3 { x: -0.5, y:  0.5, z:  0.5 }, normal: Vec3 { x: -0.5, y:  0.5, z:  0.5 }, uv: Vec2 { x: 0., y: 1. }, color: Color::from_rgba(100, 200, 30, 255) },
-            ];
-            let vertex_buffer = ctx.new_buffer(
-                BufferType::VertexBuffer,
-                BufferUsage::Immutable,
-                BufferSource::slice(&vertices),
-            );
-
-            let indices: [u16; 6] = [0, 1, 2, 0, 2, 3];
-            let index_buffer = ctx.new_buffer(
-                BufferType::IndexBuffer,
-                BufferUsage::Immutable,
-                BufferSource::slice(&indices[..]),
-            );
-
-            let pixels: [u8; 4 * 4 * 4] = [
-                0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00,
-                0x00, 0xFF, 0xFF, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0x00, 0xFF,
-                0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0x00, 0xFF, 0xFF, 0xFF,
-                0xFF, 0xFF, 0xFF, 0x00, 0x00, 0xFF, 0xFF, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
-                0xFF, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
-            ];
-            let texture = ctx.new_texture_from_rgba8(4, 4, &pixels);
-
-            let bindings = Bindings {
-                vertex_buffers: vec![vertex_buffer],
-                index_buffer,
-                images: vec![texture],
-            };
-
-            let shader = ctx
-                .new_shader(
-                    miniquad::ShaderSource::Glsl {
-                        vertex: shader::VERTEX,
-                        fragment: shader::FRAGMENT,
-                    },
-                    shader::meta(),
-                )
-                .unwrap();
-
-            let pipeline = ctx.new_pipeline(
-                &[BufferLayout::default()],
-                &[
-                    VertexAttribute::new("pos", VertexFormat::Float2),
-                    VertexAttribute::new("uv", VertexFormat::Float2),
-                ],
-                shader,
-                Default::default(),
-            );
+/// A pipeline together with the shader it was built from, both freed on drop.
+struct ShaderPipeline {
+    pipeline: Pipeline,
+    shader: ShaderId,
+}
 
-            Stage { pipeline, bindings }
-        }
+impl Drop for ShaderPipeline {
+    fn drop(&mut self) {
+        let ctx = unsafe { get_internal_gl() }.quad_context;
+        ctx.delete_pipeline(self.pipeline);
+        ctx.delete_shader(self.shader);
     }
+}
 
-    pub mod shader {
-        use macroquad::miniquad;
-        use miniquad::*;
+#[derive(Resource)]
+pub struct MeshPipeline {
+    pipeline: ShaderPipeline,
+    /// The same shaders built with `LOG_DEPTH` defined, used while drawing logarithmic depth.
+    /// Only this one writes `gl_FragDepth`, so standard depth keeps early depth testing.
+    log_depth_pipeline: ShaderPipeline,
+    cube: GpuMesh,
+    white_texture: TextureId,
+    pub light_color: Vec3,
+    pub object_color: Vec3,
+}
 
-        pub const VERTEX: &str = r#"#version 100
-attribute vec3 pos;
-attribute vec3 normal;
-attribute vec2 uv;
+impl MeshPipeline {
+    pub fn new(
+        vertex: &str,
+        fragment: &str,
+        light_color: Vec3,
+        object_color: Vec3,
+    ) -> anyhow::Result<Self> {
+        let pipeline = build_pipeline(vertex, fragment, false)?;
+        let log_depth_pipeline = build_pipeline(vertex, fragment, true)?;
+        let ctx = unsafe { get_internal_gl() }.quad_context;
+        let white_texture = ctx.new_texture_from_rgba8(1, 1, &[255, 255, 255, 255]);
 
-uniform vec2 offset;
+        Ok(Self {
+            pipeline,
+            log_depth_pipeline,
+            cube: GpuMesh::new(&unit_cube()),
+            white_texture,
+            light_color,
+            object_color,
+        })
+    }
 
-varying lowp vec2 texcoord;
+    /// Swaps in new shader sources, keeping the current pipelines if they fail to compile. The
+    /// replaced ones are deleted.
+    pub fn reload_shader(&mut self, vertex: &str, fragment: &str) -> anyhow::Result<()> {
+        let pipeline = build_pipeline(vertex, fragment, false)?;
+        self.log_depth_pipeline = build_pipeline(vertex, fragment, true)?;
+        self.pipeline = pipeline;
+        Ok(())
+    }
 
-void main() {
-    gl_Position = vec4(pos + offset, 0, 1);
-    texcoord = uv;
-}"#;
+    /// Draws already uploaded meshes with the given model matrix into the current render pass.
+    /// A positive `depth_coefficient` writes logarithmic depth instead of the standard kind.
+    pub fn draw(&self, meshes: &[GpuMesh], model: Mat4, depth_coefficient: f32) {
+        self.draw_colored(meshes, model, self.object_color, depth_coefficient);
+    }
 
-        pub const FRAGMENT: &str = r#"#version 100
-varying lowp vec2 texcoord;
+    /// Draws a lit, untextured box centered on `position`, for things too simple to have a model.
+    pub fn draw_cube(&self, position: Vec3, size: Vec3, color: Color, depth_coefficient: f32) {
+        let model = Mat4::from_scale_rotation_translation(size, Quat::IDENTITY, position);
+        let color = vec3(color.r, color.g, color.b);
+        self.draw_colored(
+            std::slice::from_ref(&self.cube),
+            model,
+            color,
+            depth_coefficient,
+        );
+    }
+
+    fn draw_colored(
+        &self,
+        meshes: &[GpuMesh],
+        model: Mat4,
+        object_color: Vec3,
+        depth_coefficient: f32,
+    ) {
+        let mut gl = unsafe { get_internal_gl() };
+        // Anything macroquad has batched so far needs to be drawn before our raw calls
+        gl.flush();
 
-uniform sampler2D tex;
+        let uniforms = MeshUniforms {
+            model,
+            projection: gl.quad_gl.get_projection_matrix(),
+            light_color: self.light_color,
+            object_color,
+            model_pos: model.w_axis.truncate(),
+            depth_coefficient,
+        };
+        let render_pass = gl.quad_gl.get_active_render_pass();
 
-void main() {
-    gl_FragColor = texture2D(tex, texcoord);
-}"#;
+        let ctx = gl.quad_context;
+        ctx.begin_pass(render_pass, PassAction::Nothing);
+        ctx.apply_pipeline(if depth_coefficient > 0. {
+            &self.log_depth_pipeline.pipeline
+        } else {
+            &self.pipeline.pipeline
+        });
+        for mesh in meshes {
+            ctx.apply_bindings(&Bindings {
+                vertex_buffers: vec![mesh.vertex_buffer],
+                index_buffer: mesh.index_buffer,
+                images: vec![mesh.texture.unwrap_or(self.white_texture)],
+            });
+            ctx.apply_uniforms(UniformsSource::table(&uniforms));
+            ctx.draw(0, mesh.index_count, 1);
+        }
+        ctx.end_render_pass();
+    }
+}
 
-        pub fn meta() -> ShaderMeta {
-            ShaderMeta {
-                images: vec!["tex".to_string()],
-                uniforms: UniformBlockLayout {
-                    uniforms: vec![UniformDesc::new("offset", UniformType::Float2)],
-                },
-            }
+/// A cube one unit across centered on the origin, with a normal per face.
+fn unit_cube() -> Mesh {
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+    for normal in [
+        Vec3::X,
+        Vec3::NEG_X,
+        Vec3::Y,
+        Vec3::NEG_Y,
+        Vec3::Z,
+        Vec3::NEG_Z,
+    ] {
+        // Two axes across the face, ordered so the corners wind counter-clockwise seen from outside
+        let u = normal.any_orthonormal_vector();
+        let v = normal.cross(u);
+        let first = vertices.len() as u16;
+        for (x, y) in [(-0.5, -0.5), (0.5, -0.5), (0.5, 0.5), (-0.5, 0.5)] {
+            vertices.push(Vertex {
+                position: normal * 0.5 + u * x + v * y,
+                uv: vec2(x + 0.5, y + 0.5),
+                color: WHITE,
+                normal,
+            });
         }
+        indices.extend([0, 1, 2, 0, 2, 3].map(|i| first + i));
+    }
+    Mesh {
+        vertices,
+        indices,
+        texture: None,
+    }
+}
 
-        #[repr(C)]
-        pub struct Uniforms {
-            pub offset: (f32, f32),
+/// Adds `#define name` to a shader, after the `#version` line that has to come first.
+fn with_define(source: &str, name: &str) -> String {
+    match source.split_once('\n') {
+        Some((version, rest)) if version.trim_start().starts_with("#version") => {
+            format!("{}\n#define {}\n{}", version, name, rest)
         }
+        _ => format!("#define {}\n{}", name, source),
     }
-}
\ No newline at end of file
+}
+
+fn build_pipeline(vertex: &str, fragment: &str, log_depth: bool) -> anyhow::Result<ShaderPipeline> {
+    let ctx = unsafe { get_internal_gl() }.quad_context;
+
+    let (vertex, fragment) = if log_depth {
+        (
+            with_define(vertex, "LOG_DEPTH"),
+            with_define(fragment, "LOG_DEPTH"),
+        )
+    } else {
+        (vertex.to_string(), fragment.to_string())
+    };
+    let shader = ctx.new_shader(
+        ShaderSource::Glsl {
+            vertex: &vertex,
+            fragment: &fragment,
+        },
+        ShaderMeta {
+            images: vec!["Texture".to_string()],
+            uniforms: UniformBlockLayout {
+                uniforms: vec![
+                    UniformDesc::new("Model", UniformType::Mat4),
+                    UniformDesc::new("Projection", UniformType::Mat4),
+                    UniformDesc::new("LightColor", UniformType::Float3),
+                    UniformDesc::new("ObjectColor", UniformType::Float3),
+                    UniformDesc::new("ModelPos", UniformType::Float3),
+                    UniformDesc::new("DepthCoefficient", UniformType::Float1),
+                ],
+            },
+        },
+    )?;
+
+    let pipeline = ctx.new_pipeline(
+        &[BufferLayout::default()],
+        &[
+            VertexAttribute::new("position", VertexFormat::Float3),
+            VertexAttribute::new("texcoord", VertexFormat::Float2),
+            VertexAttribute::new("color0", VertexFormat::Float4),
+            VertexAttribute::new("normal", VertexFormat::Float3),
+        ],
+        shader,
+        PipelineParams {
+            color_blend: Some(BlendState::new(
+                Equation::Add,
+                BlendFactor::Value(BlendValue::SourceAlpha),
+                BlendFactor::OneMinusValue(BlendValue::SourceAlpha),
+            )),
+            depth_write: true,
+            depth_test: Comparison::LessOrEqual,
+            ..Default::default()
+        },
+    );
+
+    Ok(ShaderPipeline { pipeline, shader })
+}
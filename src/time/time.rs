@@ -0,0 +1,105 @@
+use bevy_ecs::system::Resource;
+
+/// Length of a simulation step in seconds.
+pub const FIXED_DT: f32 = 1. / 60.;
+/// Steps run in a single frame at most, so a long frame slows the simulation down instead of
+/// stalling it further.
+const MAX_STEPS: u32 = 5;
+
+/// Frame and simulation clocks. Systems in the fixed update schedule should only use
+/// `fixed_delta`, so the simulation plays out the same at any frame rate.
+#[derive(Resource)]
+pub struct Time {
+    delta: f32,
+    fixed_delta: f32,
+    accumulator: f32,
+}
+
+impl Default for Time {
+    fn default() -> Self {
+        Self::new(FIXED_DT)
+    }
+}
+
+impl Time {
+    pub fn new(fixed_delta: f32) -> Self {
+        Self {
+            delta: 0.,
+            fixed_delta,
+            accumulator: 0.,
+        }
+    }
+
+    /// Duration of the last rendered frame.
+    pub fn delta(&self) -> f32 {
+        self.delta
+    }
+
+    pub fn fixed_delta(&self) -> f32 {
+        self.fixed_delta
+    }
+
+    /// How far the current frame is between the last fixed step and the next, for interpolating
+    /// what gets rendered.
+    pub fn alpha(&self) -> f32 {
+        self.accumulator / self.fixed_delta
+    }
+
+    /// Adds a frame's duration to the clocks, returning how many fixed steps are now due.
+    pub fn advance(&mut self, frame_time: f32) -> u32 {
+        self.delta = frame_time;
+
+        self.accumulator = (self.accumulator + frame_time).min(self.fixed_delta * MAX_STEPS as f32);
+        let steps = (self.accumulator / self.fixed_delta) as u32;
+        self.accumulator = (self.accumulator - steps as f32 * self.fixed_delta).max(0.);
+        steps
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Uneven frame times, like a real run, so the accumulator carries remainders over.
+    fn frame_times() -> Vec<f32> {
+        (0..1000)
+            .map(|i| 0.004 + (i as f32 * 0.37).sin().abs() * 0.03)
+            .collect()
+    }
+
+    #[test]
+    fn same_frame_times_give_same_steps() {
+        let run = || {
+            let mut time = Time::default();
+            frame_times()
+                .into_iter()
+                .map(|frame_time| (time.advance(frame_time), time.alpha().to_bits()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(run(), run());
+    }
+
+    #[test]
+    fn steps_keep_up_with_elapsed_time() {
+        let mut time = Time::default();
+        let frame_times = frame_times();
+        let steps = frame_times
+            .iter()
+            .map(|&frame_time| time.advance(frame_time))
+            .sum::<u32>();
+        let elapsed = frame_times.iter().sum::<f32>();
+        let expected = elapsed / FIXED_DT;
+        assert!(
+            (steps as f32 - expected).abs() <= 1.,
+            "{} steps for {}",
+            steps,
+            expected
+        );
+    }
+
+    #[test]
+    fn long_frames_are_capped() {
+        let mut time = Time::default();
+        assert_eq!(time.advance(10.), MAX_STEPS);
+    }
+}
@@ -1,29 +1,44 @@
-use bevy_ecs::component::Component;
-use macroquad::{
-    math::{EulerRot, Quat, Vec3},
-    models::Mesh,
+use bevy_ecs::{
+    component::Component,
+    entity::Entity,
+    system::{Commands, Query},
 };
+use macroquad::math::{Mat4, Quat, Vec3};
 
-use crate::utils::mesh_utils::Model;
-
-#[derive(Component)]
+#[derive(Component, Clone, Copy)]
 pub struct Transform {
     pub position: Vec3,
     pub scale: Vec3,
     pub rotation: Quat,
 }
 
-// TODO: Fix having to recreate meshes repeatedly. Shouldn't need to be done
 impl Transform {
-    pub fn rotate(&mut self, angle: f32, axis: Vec3) {
-        let rot = Quat::from_euler(
-            EulerRot::XYZ,
-            axis.x * angle,
-            axis.y * angle,
-            axis.z * angle,
-        );
+    pub fn model_matrix(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.position)
+    }
+
+    /// Blends from `previous` towards this transform, or just copies it if there is none.
+    pub fn interpolated(&self, previous: Option<&PreviousTransform>, t: f32) -> Transform {
+        let Some(PreviousTransform(previous)) = previous else {
+            return *self;
+        };
+        Transform {
+            position: previous.position.lerp(self.position, t),
+            scale: previous.scale.lerp(self.scale, t),
+            rotation: previous.rotation.slerp(self.rotation, t),
+        }
+    }
+
+    pub fn forward(&self) -> Vec3 {
+        self.rotation * Vec3::Z
+    }
 
-        self.rotation = rot.mul_quat(self.rotation);
+    pub fn up(&self) -> Vec3 {
+        self.rotation * Vec3::Y
+    }
+
+    pub fn right(&self) -> Vec3 {
+        self.forward().cross(self.up())
     }
 
     pub fn rescale(&mut self, x: f32, y: f32, z: f32) {
@@ -38,3 +53,24 @@ impl Transform {
         self.position.z += translation.z;
     }
 }
+
+/// Transform at the start of the current fixed step, so rendering can blend between steps.
+#[derive(Component, Clone, Copy)]
+pub struct PreviousTransform(pub Transform);
+
+/// Runs first in every fixed step.
+pub fn save_previous_transforms(
+    mut commands: Commands,
+    mut query: Query<(Entity, &Transform, Option<&mut PreviousTransform>)>,
+) {
+    for (entity, transform, previous) in query.iter_mut() {
+        match previous {
+            Some(mut previous) => previous.0 = *transform,
+            None => {
+                commands
+                    .entity(entity)
+                    .insert(PreviousTransform(*transform));
+            }
+        }
+    }
+}
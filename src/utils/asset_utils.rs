@@ -0,0 +1,232 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+use bevy_ecs::system::{ResMut, Resource};
+use macroquad::prelude::*;
+
+use crate::utils::file_utils::{archive_name, load_binary};
+use crate::utils::mesh_utils::{fallback_mesh, load_model, Model};
+
+pub const FALLBACK_COLOR: Color = MAGENTA;
+
+#[derive(Debug)]
+pub enum AssetError {
+    MissingFile(String),
+    Parse { path: String, message: String },
+    MissingMaterial { path: String, material: String },
+    MissingTexture(String),
+    IndexOverflow {
+        path: String,
+        index: u32,
+        vertex_count: usize,
+    },
+}
+
+impl fmt::Display for AssetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AssetError::MissingFile(path) => write!(f, "missing asset file {}", path),
+            AssetError::Parse { path, message } => write!(f, "failed to parse {}: {}", path, message),
+            AssetError::MissingMaterial { path, material } => {
+                write!(f, "missing material {} for {}", material, path)
+            }
+            AssetError::MissingTexture(path) => write!(f, "missing texture {}", path),
+            AssetError::IndexOverflow {
+                path,
+                index,
+                vertex_count,
+            } => write!(
+                f,
+                "{} references vertex {} but only has {} vertices",
+                path, index, vertex_count
+            ),
+        }
+    }
+}
+
+impl std::error::Error for AssetError {}
+
+/// Caches loaded assets by path so each file is only parsed and uploaded once.
+///
+/// `Model` and `Texture2D` are both reference counted, so the handles returned here are cheap
+/// to clone onto as many entities as needed. The GPU data is freed once the server has unloaded
+/// the asset and the last handle to it is dropped.
+#[derive(Resource, Default)]
+pub struct AssetServer {
+    models: HashMap<String, Model>,
+    textures: HashMap<String, Texture2D>,
+    fallback_texture: Option<Texture2D>,
+    fallback_model: Option<Model>,
+}
+
+impl AssetServer {
+    pub async fn load_model(
+        &mut self,
+        file_name: &str,
+        folder_path: &str,
+    ) -> Result<Model, AssetError> {
+        let key = asset_key(file_name, folder_path);
+        if let Some(model) = self.models.get(&key) {
+            return Ok(model.clone());
+        }
+
+        let model = load_model(file_name, folder_path, self).await?;
+        self.models.insert(key, model.clone());
+        Ok(model)
+    }
+
+    pub async fn load_texture(
+        &mut self,
+        file_name: &str,
+        folder_path: &str,
+    ) -> Result<Texture2D, AssetError> {
+        let key = asset_key(file_name, folder_path);
+        if let Some(texture) = self.textures.get(&key) {
+            return Ok(texture.clone());
+        }
+
+        let bytes = load_binary(file_name, folder_path)
+            .await
+            .map_err(|_| AssetError::MissingTexture(key.clone()))?;
+        let texture = texture_from_bytes(&bytes, &key)?;
+        self.textures.insert(key, texture.clone());
+        Ok(texture)
+    }
+
+    /// Magenta and black checkerboard used in place of textures that failed to load.
+    pub fn fallback_texture(&mut self) -> Texture2D {
+        self.fallback_texture
+            .get_or_insert_with(|| {
+                let mut image = Image::gen_image_color(8, 8, BLACK);
+                for y in 0..8 {
+                    for x in 0..8 {
+                        if (x + y) % 2 == 0 {
+                            image.set_pixel(x, y, FALLBACK_COLOR);
+                        }
+                    }
+                }
+                let texture = Texture2D::from_image(&image);
+                texture.set_filter(FilterMode::Nearest);
+                texture
+            })
+            .clone()
+    }
+
+    /// Checkerboard cube used in place of models that failed to load.
+    pub fn fallback_model(&mut self) -> Model {
+        if let Some(model) = &self.fallback_model {
+            return model.clone();
+        }
+
+        let model = Model::from_meshes(vec![fallback_mesh(self.fallback_texture())]);
+        self.fallback_model = Some(model.clone());
+        model
+    }
+
+    /// Reloads every cached model that uses one of the changed files, given as paths relative to
+    /// the asset root. Returns the replaced and replacement handle of each reloaded model along
+    /// with any load errors. Models that fail to reload stay cached as they were.
+    pub async fn reload(&mut self, changed: &[String]) -> (Vec<(Model, Model)>, Vec<AssetError>) {
+        for name in changed {
+            self.textures.remove(name);
+        }
+
+        let stale = self
+            .models
+            .keys()
+            .filter(|key| changed.iter().any(|name| uses_file(key, name)))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let mut swapped = Vec::new();
+        let mut errors = Vec::new();
+        for key in stale {
+            let path = Path::new(&key);
+            let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+            let folder_path = path.parent().unwrap_or(Path::new("")).to_string_lossy();
+            match load_model(&file_name, &folder_path, self).await {
+                Ok(model) => {
+                    if let Some(old) = self.models.insert(key, model.clone()) {
+                        swapped.push((old, model));
+                    }
+                }
+                Err(e) => errors.push(e),
+            }
+        }
+
+        (swapped, errors)
+    }
+
+    /// Number of handles to a loaded model held outside of the server.
+    pub fn model_ref_count(&self, file_name: &str, folder_path: &str) -> usize {
+        self.models
+            .get(&asset_key(file_name, folder_path))
+            .map_or(0, |model| model.ref_count() - 1)
+    }
+
+    /// Removes a model from the cache. Entities still holding it keep it alive until despawned.
+    pub fn unload_model(&mut self, file_name: &str, folder_path: &str) -> bool {
+        self.models
+            .remove(&asset_key(file_name, folder_path))
+            .is_some()
+    }
+
+    /// Removes a texture from the cache. Models still using it keep it alive until unloaded.
+    pub fn unload_texture(&mut self, file_name: &str, folder_path: &str) -> bool {
+        self.textures
+            .remove(&asset_key(file_name, folder_path))
+            .is_some()
+    }
+
+    /// Drops every cached model that is no longer used by anything outside of the server, then
+    /// every cached texture none of the remaining models use. A texture held on its own elsewhere
+    /// stays alive, it just gets loaded again the next time it's asked for.
+    pub fn unload_unused(&mut self) {
+        self.models.retain(|_, model| model.ref_count() > 1);
+
+        let used = self
+            .models
+            .values()
+            .flat_map(Model::meshes)
+            .filter_map(|mesh| mesh.texture.as_ref())
+            .map(Texture2D::raw_miniquad_id)
+            .collect::<Vec<_>>();
+        self.textures
+            .retain(|_, texture| used.contains(&texture.raw_miniquad_id()));
+    }
+}
+
+/// Unloads the models of destroyed entities once the last of them has been despawned, along
+/// with the textures only they used. Runs once a frame, as nothing is freed by repeating it
+/// between fixed steps.
+pub fn unload_unused_assets(mut assets: ResMut<AssetServer>) {
+    assets.unload_unused();
+}
+
+fn asset_key(file_name: &str, folder_path: &str) -> String {
+    archive_name(file_name, folder_path)
+}
+
+/// Whether a model may depend on a file: either the model file itself, or a material, texture or
+/// buffer sitting next to it.
+fn uses_file(model_key: &str, name: &str) -> bool {
+    let model = Path::new(model_key);
+    let file = Path::new(name);
+    let is_model = matches!(
+        file.extension().and_then(|e| e.to_str()),
+        Some("obj" | "gltf" | "glb")
+    );
+    model == file || (!is_model && model.parent() == file.parent())
+}
+
+/// Decodes an encoded image, such as a PNG, into a linearly filtered texture.
+pub fn texture_from_bytes(bytes: &[u8], path: &str) -> Result<Texture2D, AssetError> {
+    let image = Image::from_file_with_format(bytes, None).map_err(|e| AssetError::Parse {
+        path: path.to_owned(),
+        message: e.to_string(),
+    })?;
+    let texture = Texture2D::from_image(&image);
+    texture.set_filter(FilterMode::Linear);
+    Ok(texture)
+}
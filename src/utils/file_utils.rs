@@ -1,18 +1,350 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+use std::sync::OnceLock;
+
+use anyhow::{anyhow, bail};
+use flate2::read::DeflateDecoder;
+
+pub const ASSET_ROOT_ENV: &str = "SPACE_RETRO_ASSETS";
+pub const ASSET_ROOT_FLAG: &str = "--assets";
+const ASSET_FOLDER: &str = "res";
+/// Folder holding the player's settings inside the platform's config directory.
+const CONFIG_FOLDER: &str = "space_retro";
+
+// Keep in sync with the writer in build.rs
+pub const PAK_NAME: &str = "assets.pak";
+const PAK_MAGIC: &[u8; 4] = b"SRPK";
+const PAK_VERSION: u32 = 1;
+/// The archive build.rs wrote for this build, so a shipped executable needs nothing next to it.
+static EMBEDDED_PAK: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/assets.pak"));
+
+/// Source checkout whose loose assets take priority over the archive in debug builds, so edits
+/// under res/ show up without rebuilding.
+pub const DEV_ASSET_ROOT: Option<&str> = if cfg!(debug_assertions) {
+    Some(env!("CARGO_MANIFEST_DIR"))
+} else {
+    None
+};
+
+static ASSET_ROOT: OnceLock<PathBuf> = OnceLock::new();
+static ASSET_PAK: OnceLock<Option<Pak>> = OnceLock::new();
+
+struct PakEntry {
+    offset: u64,
+    compressed_size: u64,
+    size: u64,
+    crc: u32,
+}
+
+/// Where an archive's bytes are read from.
+enum PakSource {
+    File(PathBuf),
+    /// Built into the executable.
+    Embedded(&'static [u8]),
+}
+
+impl fmt::Display for PakSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PakSource::File(path) => write!(f, "{}", path.display()),
+            PakSource::Embedded(_) => write!(f, "the embedded asset archive"),
+        }
+    }
+}
+
+/// Read only view of the asset archive written by build.rs. Only the index is kept in memory,
+/// file contents are read and decompressed on demand.
+pub struct Pak {
+    source: PakSource,
+    entries: HashMap<String, PakEntry>,
+}
+
+impl Pak {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let source = PakSource::File(path.to_path_buf());
+        let entries = read_index(&mut File::open(path)?, &source)?;
+        Ok(Self { source, entries })
+    }
+
+    /// The archive built into the executable.
+    pub fn embedded() -> anyhow::Result<Self> {
+        let source = PakSource::Embedded(EMBEDDED_PAK);
+        let entries = read_index(&mut Cursor::new(EMBEDDED_PAK), &source)?;
+        Ok(Self { source, entries })
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.entries.contains_key(name)
+    }
+
+    pub fn read(&self, name: &str) -> anyhow::Result<Vec<u8>> {
+        let entry = self
+            .entries
+            .get(name)
+            .ok_or_else(|| anyhow!("{} is not in {}", name, self.source))?;
+
+        let compressed: Box<dyn Read> = match &self.source {
+            PakSource::File(path) => {
+                let mut file = File::open(path)?;
+                file.seek(SeekFrom::Start(entry.offset))?;
+                Box::new(file.take(entry.compressed_size))
+            }
+            PakSource::Embedded(bytes) => {
+                let start = entry.offset as usize;
+                let end = start.saturating_add(entry.compressed_size as usize);
+                Box::new(
+                    bytes
+                        .get(start..end)
+                        .ok_or_else(|| anyhow!("{} in {} is truncated", name, self.source))?,
+                )
+            }
+        };
+        let mut data = Vec::with_capacity(entry.size as usize);
+        DeflateDecoder::new(compressed).read_to_end(&mut data)?;
+        if crc32fast::hash(&data) != entry.crc {
+            bail!("{} in {} is corrupted", name, self.source);
+        }
+
+        Ok(data)
+    }
+
+    /// Decompresses every entry and returns the names of the ones that fail their checksum.
+    pub fn verify(&self) -> Vec<String> {
+        self.entries
+            .keys()
+            .filter(|name| self.read(name).is_err())
+            .cloned()
+            .collect()
+    }
+}
+
+fn read_index(
+    reader: &mut impl Read,
+    source: &PakSource,
+) -> anyhow::Result<HashMap<String, PakEntry>> {
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != PAK_MAGIC {
+        bail!("{} is not an asset archive", source);
+    }
+    let version = read_u32(reader)?;
+    if version != PAK_VERSION {
+        bail!(
+            "{} has version {} but version {} is required",
+            source,
+            version,
+            PAK_VERSION
+        );
+    }
+
+    let count = read_u32(reader)?;
+    let mut entries = HashMap::new();
+    for _ in 0..count {
+        let mut name_len = [0; 2];
+        reader.read_exact(&mut name_len)?;
+        let mut name = vec![0; u16::from_le_bytes(name_len) as usize];
+        reader.read_exact(&mut name)?;
+        let entry = PakEntry {
+            offset: read_u64(reader)?,
+            compressed_size: read_u64(reader)?,
+            size: read_u64(reader)?,
+            crc: read_u32(reader)?,
+        };
+        entries.insert(String::from_utf8(name)?, entry);
+    }
+    Ok(entries)
+}
+
+fn read_u32(reader: &mut impl Read) -> anyhow::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(reader: &mut impl Read) -> anyhow::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+/// Directory the asset folders are resolved against, looked up once on first use.
+///
+/// In order of priority this is the `--assets <dir>` command line flag, the
+/// `SPACE_RETRO_ASSETS` environment variable, the directory of the executable if it has a `res`
+/// folder or asset archive next to it, the build's `OUT_DIR` in debug builds and finally the
+/// working directory.
+pub fn asset_root() -> &'static Path {
+    ASSET_ROOT.get_or_init(|| {
+        let root = find_asset_root();
+        println!("Asset root: {}", root.display());
+        root
+    })
+}
+
+fn find_asset_root() -> PathBuf {
+    let mut args = std::env::args();
+    while let Some(arg) = args.next() {
+        if arg == ASSET_ROOT_FLAG {
+            if let Some(root) = args.next() {
+                return PathBuf::from(root);
+            }
+        } else if let Some(root) = arg.strip_prefix("--assets=") {
+            return PathBuf::from(root);
+        }
+    }
+
+    if let Some(root) = std::env::var_os(ASSET_ROOT_ENV) {
+        return PathBuf::from(root);
+    }
+
+    let exe_dir = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf));
+    exe_dir
+        .into_iter()
+        .chain(cfg!(debug_assertions).then(|| PathBuf::from(env!("OUT_DIR"))))
+        .find(|dir| dir.join(ASSET_FOLDER).is_dir() || dir.join(PAK_NAME).is_file())
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// Per-user directory the player's settings are saved to, kept apart from the assets since those
+/// may be read-only or wiped by a rebuild.
+///
+/// This is `%APPDATA%` on Windows, `~/Library/Application Support` on macOS and
+/// `$XDG_CONFIG_HOME` or `~/.config` elsewhere, falling back to the working directory when none of
+/// those are set.
+pub fn config_dir() -> PathBuf {
+    let var = |name: &str| std::env::var_os(name).map(PathBuf::from);
+    let base = if cfg!(windows) {
+        var("APPDATA")
+    } else if cfg!(target_os = "macos") {
+        var("HOME").map(|home| home.join("Library/Application Support"))
+    } else {
+        var("XDG_CONFIG_HOME").or_else(|| var("HOME").map(|home| home.join(".config")))
+    };
+    base.map_or_else(|| PathBuf::from("."), |base| base.join(CONFIG_FOLDER))
+}
+
+/// The asset archive in the asset root if there is one, so a newer archive can be dropped in
+/// without rebuilding, otherwise the one built into the executable.
+pub fn asset_pak() -> Option<&'static Pak> {
+    ASSET_PAK
+        .get_or_init(|| {
+            let path = asset_root().join(PAK_NAME);
+            let pak = if path.is_file() {
+                Pak::open(&path)
+            } else {
+                Pak::embedded()
+            };
+            pak.map_err(|e| println!("Failed to open asset archive: {}", e))
+                .ok()
+        })
+        .as_ref()
+}
+
+/// Checks every file in the asset archive, returning the ones that are corrupted.
+pub fn verify_assets() -> Vec<String> {
+    asset_pak().map(Pak::verify).unwrap_or_default()
+}
+
+pub fn asset_path(file_name: &str, folder_path: &str) -> PathBuf {
+    asset_root().join(folder_path).join(file_name)
+}
+
+/// Name of an asset inside the archive, always `/` separated and relative to the asset root.
+pub fn archive_name(file_name: &str, folder_path: &str) -> String {
+    let mut parts = Vec::new();
+    for component in Path::new(folder_path).join(file_name).components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_string_lossy().into_owned()),
+            Component::ParentDir => {
+                parts.pop();
+            }
+            _ => {}
+        }
+    }
+    parts.join("/")
+}
+
+/// Reads an asset, preferring a loose file in the asset root so mods can override anything in
+/// the archive, then the source checkout in debug builds.
+fn read_asset(file_name: &str, folder_path: &str) -> anyhow::Result<Vec<u8>> {
+    let path = asset_path(file_name, folder_path);
+    let dev_path = DEV_ASSET_ROOT.map(|root| Path::new(root).join(folder_path).join(file_name));
+    for loose in [Some(path.clone()), dev_path].into_iter().flatten() {
+        if loose.is_file() {
+            return Ok(std::fs::read(loose)?);
+        }
+    }
+
+    let name = archive_name(file_name, folder_path);
+    match asset_pak() {
+        Some(pak) if pak.contains(&name) => pak.read(&name),
+        _ => bail!("{} not found", path.display()),
+    }
+}
+
 pub async fn load_string(file_name: &str, folder_path: &str) -> anyhow::Result<String> {
-    let path = std::path::Path::new(env!("OUT_DIR"))
-        .join(folder_path)
-        .join(file_name);
-    println!("Path: {}", path.to_str().unwrap());
-    let txt = std::fs::read_to_string(path)?;
+    println!("Path: {}", archive_name(file_name, folder_path));
+    let txt = String::from_utf8(read_asset(file_name, folder_path)?)?;
 
     Ok(txt)
 }
 
-pub async fn load_binary(file_name: &str) -> anyhow::Result<Vec<u8>> {
-    let path = std::path::Path::new(env!("OUT_DIR"))
-        .join("res")
-        .join(file_name);
-    let data = std::fs::read(path)?;
+pub async fn load_binary(file_name: &str, folder_path: &str) -> anyhow::Result<Vec<u8>> {
+    let data = read_asset(file_name, folder_path)?;
 
     Ok(data)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The archive build.rs wrote for this build has to read back as exactly the files in res/,
+    /// both from disk and as embedded in the executable.
+    #[test]
+    fn pak_matches_res() {
+        let on_disk = Pak::open(&Path::new(env!("OUT_DIR")).join(PAK_NAME)).unwrap();
+        for pak in [on_disk, Pak::embedded().unwrap()] {
+            check_pak(&pak);
+        }
+    }
+
+    fn check_pak(pak: &Pak) {
+        assert!(pak.verify().is_empty());
+
+        let mut files = Vec::new();
+        let mut dirs = vec![PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(ASSET_FOLDER)];
+        while let Some(dir) = dirs.pop() {
+            for entry in std::fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    dirs.push(path);
+                } else {
+                    files.push(path);
+                }
+            }
+        }
+        assert_eq!(files.len(), pak.entries.len());
+
+        for path in files {
+            let name = path
+                .strip_prefix(env!("CARGO_MANIFEST_DIR"))
+                .unwrap()
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            assert_eq!(
+                pak.read(&name).unwrap(),
+                std::fs::read(&path).unwrap(),
+                "{}",
+                name
+            );
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use gltf::{buffer, image, mesh::Mode, Gltf, Node};
+use macroquad::prelude::*;
+
+use crate::utils::asset_utils::{texture_from_bytes, AssetError, AssetServer};
+use crate::utils::file_utils::{archive_name, load_binary};
+use crate::utils::mesh_utils::{build_meshes, Model};
+
+/// Loads a glTF 2.0 (`.gltf` or `.glb`) file. The node hierarchy of the default scene is
+/// flattened, with each node's transform baked into its mesh vertices.
+pub async fn load_gltf(
+    file_name: &str,
+    folder_path: &str,
+    assets: &mut AssetServer,
+) -> Result<Model, AssetError> {
+    let path = archive_name(file_name, folder_path);
+    let parse_error = |message: String| AssetError::Parse {
+        path: path.clone(),
+        message,
+    };
+
+    let bytes = load_binary(file_name, folder_path)
+        .await
+        .map_err(|_| AssetError::MissingFile(path.clone()))?;
+    let gltf = Gltf::from_slice(&bytes).map_err(|e| parse_error(e.to_string()))?;
+
+    let mut buffers = Vec::new();
+    for buffer in gltf.buffers() {
+        let data = match buffer.source() {
+            buffer::Source::Bin => gltf
+                .blob
+                .clone()
+                .ok_or_else(|| parse_error("missing binary chunk".to_owned()))?,
+            buffer::Source::Uri(uri) => load_uri(uri, folder_path).await?,
+        };
+        buffers.push(data);
+    }
+
+    let mut textures = Vec::new();
+    for image in gltf.images() {
+        let texture = match image.source() {
+            image::Source::View { view, .. } => buffers
+                .get(view.buffer().index())
+                .and_then(|buffer| buffer.get(view.offset()..view.offset() + view.length()))
+                .ok_or_else(|| parse_error("image view outside its buffer".to_owned()))
+                .and_then(|data| texture_from_bytes(data, &path)),
+            image::Source::Uri { uri, .. } if uri.starts_with("data:") => {
+                load_uri(uri, folder_path)
+                    .await
+                    .and_then(|data| texture_from_bytes(&data, &path))
+            }
+            image::Source::Uri { uri, .. } => assets.load_texture(uri, folder_path).await,
+        };
+        textures.push(texture.unwrap_or_else(|e| {
+            warn!("{}", e);
+            assets.fallback_texture()
+        }));
+    }
+
+    let scene = gltf
+        .default_scene()
+        .or_else(|| gltf.scenes().next())
+        .ok_or_else(|| parse_error("no scene".to_owned()))?;
+
+    let mut meshes = Vec::new();
+    let mut nodes: Vec<(Node, Mat4)> = scene.nodes().map(|n| (n, Mat4::IDENTITY)).collect();
+    while let Some((node, parent_transform)) = nodes.pop() {
+        let transform = parent_transform * Mat4::from_cols_array_2d(&node.transform().matrix());
+        let normal_matrix = Mat3::from_mat4(transform).inverse().transpose();
+
+        for primitive in node.mesh().iter().flat_map(|mesh| mesh.primitives()) {
+            if primitive.mode() != Mode::Triangles {
+                warn!("Skipping non triangle primitive in {}", path);
+                continue;
+            }
+
+            let reader = primitive.reader(|b| buffers.get(b.index()).map(Vec::as_slice));
+            let positions = reader
+                .read_positions()
+                .ok_or_else(|| parse_error("primitive without positions".to_owned()))?
+                .map(|p| transform.transform_point3(Vec3::from(p)))
+                .collect::<Vec<_>>();
+            let normals = reader.read_normals().map(|normals| {
+                normals
+                    .map(|n| (normal_matrix * Vec3::from(n)).normalize_or_zero())
+                    .collect()
+            });
+            // glTF puts the UV origin at the top left, the mesh shader expects it at the bottom
+            let uvs = reader
+                .read_tex_coords(0)
+                .map(|uvs| uvs.into_f32().map(|[u, v]| vec2(u, 1. - v)).collect());
+
+            let pbr = primitive.material().pbr_metallic_roughness();
+            let base_color = Vec4::from(pbr.base_color_factor());
+            let colors = reader
+                .read_colors(0)
+                .map(|colors| {
+                    colors
+                        .into_rgba_f32()
+                        .map(|c| Color::from_vec(Vec4::from(c) * base_color))
+                        .collect::<Vec<_>>()
+                })
+                .filter(|colors| colors.len() == positions.len())
+                .unwrap_or_else(|| vec![Color::from_vec(base_color); positions.len()]);
+            let texture = pbr
+                .base_color_texture()
+                .and_then(|info| textures.get(info.texture().source().index()).cloned());
+
+            let indices = match reader.read_indices() {
+                Some(indices) => indices.into_u32().collect(),
+                None => (0..positions.len() as u32).collect::<Vec<_>>(),
+            };
+
+            meshes.extend(build_meshes(
+                &path, &positions, normals, uvs, colors, &indices, texture,
+            )?);
+        }
+
+        nodes.extend(node.children().map(|child| (child, transform)));
+    }
+
+    Ok(Model::from_meshes(meshes))
+}
+
+async fn load_uri(uri: &str, folder_path: &str) -> Result<Vec<u8>, AssetError> {
+    match uri.strip_prefix("data:") {
+        Some(data) => {
+            let encoded = data.split_once(";base64,").map_or("", |(_, encoded)| encoded);
+            STANDARD.decode(encoded).map_err(|e| AssetError::Parse {
+                path: uri.chars().take(32).collect(),
+                message: e.to_string(),
+            })
+        }
+        None => load_binary(uri, folder_path)
+            .await
+            .map_err(|_| AssetError::MissingFile(folder_path.to_owned() + uri)),
+    }
+}
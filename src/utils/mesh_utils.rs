@@ -1,71 +1,115 @@
-use bevy_ecs::system::{ResMut, Resource};
+use bevy_ecs::system::Res;
 use bevy_ecs::{component::Component, system::Query};
 use macroquad::{file, prelude::*, text};
+use std::collections::HashMap;
 use std::io::{BufReader, Cursor};
 use std::path::Path;
-
-use crate::transform::transform::Transform;
-use crate::utils::file_utils::load_string;
-
-#[derive(Resource)]
-pub struct BaseMeshMaterial {
-    pub material: Material,
+use std::sync::Arc;
+
+use crate::camera::camera::CameraState;
+use crate::renderer::renderer::{GpuMesh, MeshPipeline};
+use crate::time::time::Time;
+use crate::transform::transform::{PreviousTransform, Transform};
+use crate::utils::asset_utils::{AssetError, AssetServer, FALLBACK_COLOR};
+use crate::utils::file_utils::{archive_name, load_string};
+use crate::utils::gltf_utils::load_gltf;
+
+struct ModelData {
+    meshes: Vec<Mesh>,
+    gpu_meshes: Vec<GpuMesh>,
 }
 
-#[derive(Component)]
+/// Shared handle to a loaded model. Cloning it is cheap and does not duplicate any mesh data.
+#[derive(Component, Clone)]
 pub struct Model {
-    pub meshes: Vec<Mesh>,
+    data: Arc<ModelData>,
 }
 
 impl Model {
-    pub async fn new(file_name: &str, folder_path: &str) -> Self {
-        // TODO: Proper error handling
-        load_model(file_name, folder_path).await.unwrap()
+    /// Loads a model through the asset cache, falling back to a checkerboard cube on failure.
+    pub async fn new(file_name: &str, folder_path: &str, assets: &mut AssetServer) -> Self {
+        match assets.load_model(file_name, folder_path).await {
+            Ok(model) => model,
+            Err(e) => {
+                warn!("{}", e);
+                assets.fallback_model()
+            }
+        }
     }
 
-    pub fn draw(&self, transform: &Transform) {
-        for mesh in &self.meshes {
-            draw_mesh(&get_transformed_mesh(mesh, transform));
+    pub fn from_meshes(meshes: Vec<Mesh>) -> Self {
+        let gpu_meshes = meshes.iter().map(GpuMesh::new).collect();
+        Self {
+            data: Arc::new(ModelData { meshes, gpu_meshes }),
         }
     }
-}
 
-pub fn get_transformed_mesh(mesh: &Mesh, transform: &Transform) -> Mesh {
-    let mut new_m = Mesh {
-        vertices: mesh.vertices.clone(),
-        indices: mesh.indices.clone(),
-        texture: mesh.texture.clone(),
-    };
+    pub fn meshes(&self) -> &[Mesh] {
+        &self.data.meshes
+    }
 
-    for v in 0..new_m.vertices.len() {
-        new_m.vertices[v].position.x *= transform.scale.x;
-        new_m.vertices[v].position.y *= transform.scale.y;
-        new_m.vertices[v].position.z *= transform.scale.z;
+    pub fn ptr_eq(&self, other: &Model) -> bool {
+        Arc::ptr_eq(&self.data, &other.data)
+    }
 
-        new_m.vertices[v].position = transform
-            .rotation
-            .mul_vec3(new_m.vertices[v].position - transform.position)
-            + transform.position;
+    pub(crate) fn ref_count(&self) -> usize {
+        Arc::strong_count(&self.data)
+    }
 
-        new_m.vertices[v].position.x += transform.position.x;
-        new_m.vertices[v].position.y += transform.position.y;
-        new_m.vertices[v].position.z += transform.position.z;
+    pub fn draw(
+        &self,
+        transform: &Transform,
+        mesh_pipeline: &MeshPipeline,
+        depth_coefficient: f32,
+    ) {
+        mesh_pipeline.draw(
+            &self.data.gpu_meshes,
+            transform.model_matrix(),
+            depth_coefficient,
+        );
     }
+}
 
-    return new_m;
+/// Draws every model blended between its last two fixed steps.
+pub fn draw_models(
+    query: Query<(&Model, &Transform, Option<&PreviousTransform>)>,
+    mesh_pipeline: Res<MeshPipeline>,
+    camera: Res<CameraState>,
+    time: Res<Time>,
+) {
+    for (model, transform, previous) in query.iter() {
+        model.draw(
+            &transform.interpolated(previous, time.alpha()),
+            &mesh_pipeline,
+            camera.depth_coefficient(),
+        );
+    }
 }
 
-pub fn draw_models(query: Query<(&Model, &Transform)>, base_mesh_material: ResMut<BaseMeshMaterial>) {
-    for (model, transform) in query.iter() {
-        base_mesh_material.material.set_uniform("ModelPos", <(f32, f32, f32)>::from(transform.position));
-        gl_use_material(&base_mesh_material.material);
-        model.draw(transform);
-        gl_use_default_material();
+pub async fn load_model(
+    file_name: &str,
+    folder_path: &str,
+    assets: &mut AssetServer,
+) -> Result<Model, AssetError> {
+    let extension = Path::new(file_name)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase);
+    match extension.as_deref() {
+        Some("gltf") | Some("glb") => load_gltf(file_name, folder_path, assets).await,
+        _ => load_obj(file_name, folder_path, assets).await,
     }
 }
 
-pub async fn load_model(file_name: &str, folder_path: &str) -> anyhow::Result<Model> {
-    let obj_text = load_string(file_name, folder_path).await?;
+pub async fn load_obj(
+    file_name: &str,
+    folder_path: &str,
+    assets: &mut AssetServer,
+) -> Result<Model, AssetError> {
+    let path = archive_name(file_name, folder_path);
+    let obj_text = load_string(file_name, folder_path)
+        .await
+        .map_err(|_| AssetError::MissingFile(path.clone()))?;
     let obj_cursor = Cursor::new(obj_text);
     let mut obj_reader = BufReader::new(obj_cursor);
 
@@ -77,113 +121,260 @@ pub async fn load_model(file_name: &str, folder_path: &str) -> anyhow::Result<Mo
             ..Default::default()
         },
         |p| async move {
-            let mat_text: String = load_string(&p, folder_path).await.unwrap();
+            let Ok(mat_text) = load_string(&p, folder_path).await else {
+                return Err(tobj::LoadError::OpenFileFailed);
+            };
             tobj::load_mtl_buf(&mut BufReader::new(Cursor::new(mat_text)))
         },
     )
-    .await?;
-
-    let mut textures: Vec<Texture2D> = Vec::new();
-    for m in obj_materials? {
-        let texture_path = &m.diffuse_texture.unwrap_or_default();
-        let final_path = folder_path.to_owned() + texture_path;
-        println!("{}", final_path);
-        let diffuse_texture = load_texture(&final_path).await?;
-        diffuse_texture.set_filter(FilterMode::Linear);
+    .await
+    .map_err(|e| AssetError::Parse {
+        path: path.clone(),
+        message: e.to_string(),
+    })?;
+
+    let obj_materials = obj_materials.unwrap_or_else(|e| {
+        warn!(
+            "{}",
+            AssetError::MissingMaterial {
+                path: path.clone(),
+                material: e.to_string(),
+            }
+        );
+        Vec::new()
+    });
+
+    let mut materials: Vec<(Option<Texture2D>, Color)> = Vec::new();
+    for m in obj_materials {
+        let color = m
+            .diffuse
+            .map_or(WHITE, |[r, g, b]| Color::new(r, g, b, 1.));
+        let texture = match m.diffuse_texture {
+            Some(texture_path) => {
+                match assets.load_texture(&texture_path, folder_path).await {
+                    Ok(texture) => Some(texture),
+                    Err(e) => {
+                        warn!("{}", e);
+                        Some(assets.fallback_texture())
+                    }
+                }
+            }
+            None => None,
+        };
         //let normal_texture = Some(load_texture(&m.normal_texture, true, device, queue).await?);
 
-        textures.push(diffuse_texture);
+        materials.push((texture, color));
     }
 
-    let meshes = models
-        .into_iter()
-        .map(|m| {
-            let vertices = (0..m.mesh.positions.len() / 3)
-                .map(|i| macroquad::models::Vertex {
-                    position: vec3(
-                        m.mesh.positions[i * 3],
-                        m.mesh.positions[i * 3 + 1],
-                        m.mesh.positions[i * 3 + 2],
-                    ),
-                    uv: vec2(m.mesh.texcoords[i * 2], m.mesh.texcoords[i * 2 + 1]),
-                    color: WHITE,
-                    normal: vec3(
-                        m.mesh.normals[i * 3],
-                        m.mesh.normals[i * 3 + 1],
-                        m.mesh.normals[i * 3 + 2],
-                    ),
-                })
-                .collect::<Vec<_>>();
-
-            let mut indices: Vec<u16> = vec![];
-            for i in m.mesh.indices {
-                indices.push(i as u16);
-            }
-
-            Mesh {
-                vertices,
-                indices,
-                texture: Some(textures[m.mesh.material_id.unwrap_or(0)].clone()),
-            }
-        })
-        .collect::<Vec<_>>();
+    let mut meshes = Vec::new();
+    for m in models {
+        let (texture, color) = match m.mesh.material_id {
+            Some(id) => materials.get(id).cloned().unwrap_or_else(|| {
+                warn!(
+                    "{}",
+                    AssetError::MissingMaterial {
+                        path: path.clone(),
+                        material: id.to_string(),
+                    }
+                );
+                (None, FALLBACK_COLOR)
+            }),
+            None => (None, WHITE),
+        };
+
+        let positions = m
+            .mesh
+            .positions
+            .chunks_exact(3)
+            .map(|p| vec3(p[0], p[1], p[2]))
+            .collect::<Vec<_>>();
+        let normals = (m.mesh.normals.len() == m.mesh.positions.len()).then(|| {
+            m.mesh
+                .normals
+                .chunks_exact(3)
+                .map(|n| vec3(n[0], n[1], n[2]))
+                .collect::<Vec<_>>()
+        });
+        let uvs = (m.mesh.texcoords.len() / 2 == positions.len()).then(|| {
+            m.mesh
+                .texcoords
+                .chunks_exact(2)
+                .map(|t| vec2(t[0], t[1]))
+                .collect::<Vec<_>>()
+        });
+
+        meshes.extend(build_meshes(
+            &path,
+            &positions,
+            normals,
+            uvs,
+            vec![color; positions.len()],
+            &m.mesh.indices,
+            texture,
+        )?);
+    }
 
-    Ok(Model { meshes })
+    Ok(Model::from_meshes(meshes))
 }
 
-/// Basic Mesh Shaders
-pub(crate) const MESH_FRAGMENT_SHADER: &'static str = r#"#version 330
-precision mediump float;
+/// Builds renderable meshes from raw triangle data, filling in normals and UVs the source file
+/// left out and splitting the result into chunks small enough for 16 bit indices.
+pub fn build_meshes(
+    path: &str,
+    positions: &[Vec3],
+    normals: Option<Vec<Vec3>>,
+    uvs: Option<Vec<Vec2>>,
+    colors: Vec<Color>,
+    indices: &[u32],
+    texture: Option<Texture2D>,
+) -> Result<Vec<Mesh>, AssetError> {
+    if let Some(&index) = indices.iter().find(|&&i| i as usize >= positions.len()) {
+        return Err(AssetError::IndexOverflow {
+            path: path.to_owned(),
+            index,
+            vertex_count: positions.len(),
+        });
+    }
 
-in lowp vec2 uv;
-in vec3 Normal;
-in vec3 FragPos;
+    if colors.len() != positions.len() {
+        return Err(AssetError::Parse {
+            path: path.to_owned(),
+            message: format!(
+                "{} vertex colors for {} positions",
+                colors.len(),
+                positions.len()
+            ),
+        });
+    }
 
-out vec4 diffuseColor;
+    // Attributes that don't cover every vertex are regenerated, the same as if they were missing
+    let normals = normals
+        .filter(|normals| normals.len() == positions.len())
+        .unwrap_or_else(|| generate_normals(positions, indices));
+    let uvs = uvs
+        .filter(|uvs| uvs.len() == positions.len())
+        .unwrap_or_else(|| generate_uvs(positions, &normals));
+    let vertices = (0..positions.len())
+        .map(|i| macroquad::models::Vertex {
+            position: positions[i],
+            uv: uvs[i],
+            color: colors[i],
+            normal: normals[i],
+        })
+        .collect::<Vec<_>>();
 
-uniform vec3 LightColor;
-uniform vec3 ObjectColor;
-uniform sampler2D Texture;
+    Ok(split_mesh(&vertices, indices)
+        .into_iter()
+        .map(|(vertices, indices)| Mesh {
+            vertices,
+            indices,
+            texture: texture.clone(),
+        })
+        .collect())
+}
 
-void main() {
-    float ambientStrength = 0.1;
-    vec3 ambient = ambientStrength * LightColor;
+/// Averages the normals of every triangle sharing a vertex, weighted by triangle area. Vertices
+/// that are shared between faces end up smooth shaded, while faces with their own vertices stay
+/// flat.
+pub fn generate_normals(positions: &[Vec3], indices: &[u32]) -> Vec<Vec3> {
+    let mut normals = vec![Vec3::ZERO; positions.len()];
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| triangle[i] as usize);
+        let face_normal = (positions[b] - positions[a]).cross(positions[c] - positions[a]);
+        normals[a] += face_normal;
+        normals[b] += face_normal;
+        normals[c] += face_normal;
+    }
 
-    //vec3 result = ambient * ObjectColor;
-    
-    vec3 norm = normalize(Normal);
-    vec3 lightDir = vec3(-0.25, 0.25, 0.5);
+    normals
+        .into_iter()
+        .map(|n| n.try_normalize().unwrap_or(Vec3::Y))
+        .collect()
+}
 
-    float diff = max(dot(norm, lightDir), 0.0);
-    vec3 diffuse = diff * LightColor;
+/// Box projects positions onto the plane facing each vertex normal, normalized to the bounds of
+/// the mesh so a texture covers it once per side.
+pub fn generate_uvs(positions: &[Vec3], normals: &[Vec3]) -> Vec<Vec2> {
+    let min = positions.iter().fold(Vec3::splat(f32::MAX), |a, &b| a.min(b));
+    let max = positions.iter().fold(Vec3::splat(f32::MIN), |a, &b| a.max(b));
+    let extent = (max - min).max(Vec3::splat(f32::EPSILON));
+
+    positions
+        .iter()
+        .zip(normals)
+        .map(|(&p, n)| {
+            let p = (p - min) / extent;
+            let n = n.abs();
+            if n.x >= n.y && n.x >= n.z {
+                vec2(p.z, p.y)
+            } else if n.y >= n.z {
+                vec2(p.x, p.z)
+            } else {
+                vec2(p.x, p.y)
+            }
+        })
+        .collect()
+}
 
-    vec3 result = (ambient + diffuse) * ObjectColor;
-    //FragColor = vec4(result, 1.0);
-    vec2 updatedUV = vec2(uv.x, 1.0 - uv.y);
+/// Splits an indexed triangle list into pieces that each address at most `u16::MAX + 1` vertices.
+fn split_mesh(vertices: &[Vertex], indices: &[u32]) -> Vec<(Vec<Vertex>, Vec<u16>)> {
+    const MAX_VERTICES: usize = u16::MAX as usize + 1;
 
-    diffuseColor = vec4(result, 1.0) * texture(Texture, updatedUV);
-}
-"#;
+    if vertices.len() <= MAX_VERTICES {
+        return vec![(vertices.to_vec(), indices.iter().map(|&i| i as u16).collect())];
+    }
 
-pub(crate) const MESH_VERTEX_SHADER: &'static str = "#version 330
-precision mediump float;
+    let mut chunks = Vec::new();
+    let mut chunk_vertices: Vec<Vertex> = Vec::new();
+    let mut chunk_indices: Vec<u16> = Vec::new();
+    let mut remap: HashMap<u32, u16> = HashMap::new();
+    for triangle in indices.chunks_exact(3) {
+        if chunk_vertices.len() + 3 > MAX_VERTICES {
+            chunks.push((
+                std::mem::take(&mut chunk_vertices),
+                std::mem::take(&mut chunk_indices),
+            ));
+            remap.clear();
+        }
 
-in vec3 position;
-in vec2 texcoord;
-in vec4 color0;
-in vec3 normal;
+        for &index in triangle {
+            let local = *remap.entry(index).or_insert_with(|| {
+                chunk_vertices.push(vertices[index as usize]);
+                (chunk_vertices.len() - 1) as u16
+            });
+            chunk_indices.push(local);
+        }
+    }
+    if !chunk_indices.is_empty() {
+        chunks.push((chunk_vertices, chunk_indices));
+    }
 
-out lowp vec2 uv;
-out vec3 Normal;
-out vec3 FragPos;
+    chunks
+}
 
-uniform mat4 Model;
-uniform mat4 Projection;
+/// Unit cube with outward normals, used to stand in for models that failed to load.
+pub fn fallback_mesh(texture: Texture2D) -> Mesh {
+    let faces = [Vec3::X, -Vec3::X, Vec3::Y, -Vec3::Y, Vec3::Z, -Vec3::Z];
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+    for normal in faces {
+        let tangent = if normal.y.abs() > 0.5 { Vec3::X } else { Vec3::Y };
+        let bitangent = normal.cross(tangent);
+        let start = vertices.len() as u16;
+        for (u, v) in [(0., 0.), (1., 0.), (1., 1.), (0., 1.)] {
+            vertices.push(macroquad::models::Vertex {
+                position: (normal + tangent * (u * 2. - 1.) + bitangent * (v * 2. - 1.)) * 0.5,
+                uv: vec2(u, v),
+                color: FALLBACK_COLOR,
+                normal,
+            });
+        }
+        indices.extend([start, start + 1, start + 2, start, start + 2, start + 3]);
+    }
 
-void main() {
-    gl_Position = Projection * Model * vec4(position, 1);
-    uv = texcoord;
-    Normal = normal;
-    FragPos = vec3(Model * vec4(position, 1.0));
+    Mesh {
+        vertices,
+        indices,
+        texture: Some(texture),
+    }
 }
-";
@@ -1,3 +1,6 @@
+pub mod asset_utils;
 pub mod file_utils;
+pub mod gltf_utils;
 pub mod input_utils;
 pub mod mesh_utils;
+pub mod reload_utils;
@@ -0,0 +1,195 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use bevy_ecs::world::World;
+use macroquad::prelude::*;
+
+use crate::combat::destruction::Respawning;
+use crate::physics::collider::Collider;
+use crate::projectiles::projectile::Armament;
+use crate::utils::asset_utils::AssetServer;
+use crate::utils::file_utils::{load_string, DEV_ASSET_ROOT};
+use crate::utils::mesh_utils::Model;
+
+const POLL_INTERVAL: f64 = 0.5;
+pub const SHADER_FOLDER: &str = "res/shaders/";
+
+/// Development only watcher that polls the source checkout's res/ folder for modified files and
+/// reloads the models and shaders using them.
+pub struct HotReload {
+    root: PathBuf,
+    modified: HashMap<PathBuf, SystemTime>,
+    last_poll: f64,
+    /// Most recent reload failure of the models and of each shader, by vertex shader name. Each
+    /// is cleared once that same thing reloads fine.
+    errors: BTreeMap<String, String>,
+}
+
+impl HotReload {
+    /// Returns `None` in release builds, where there is no source checkout to watch.
+    pub fn new() -> Option<Self> {
+        let root = PathBuf::from(DEV_ASSET_ROOT?);
+        let mut hot_reload = Self {
+            root,
+            modified: HashMap::new(),
+            last_poll: get_time(),
+            errors: BTreeMap::new(),
+        };
+        hot_reload.scan();
+        Some(hot_reload)
+    }
+
+    /// Paths relative to the asset root, like `res/test.obj`, of every file modified since the
+    /// last poll.
+    pub fn changed_files(&mut self) -> Vec<String> {
+        if get_time() - self.last_poll < POLL_INTERVAL {
+            return Vec::new();
+        }
+        self.last_poll = get_time();
+        self.scan()
+    }
+
+    fn scan(&mut self) -> Vec<String> {
+        let mut files = Vec::new();
+        collect_files(&self.root.join("res"), &mut files);
+
+        let mut changed = Vec::new();
+        for file in files {
+            let Ok(modified) = file.metadata().and_then(|m| m.modified()) else {
+                continue;
+            };
+            if self.modified.insert(file.clone(), modified) != Some(modified) {
+                if let Ok(relative) = file.strip_prefix(&self.root) {
+                    changed.push(
+                        relative
+                            .components()
+                            .map(|c| c.as_os_str().to_string_lossy())
+                            .collect::<Vec<_>>()
+                            .join("/"),
+                    );
+                }
+            }
+        }
+        changed
+    }
+
+    /// Reloads every cached model affected by the changed files and swaps the new versions into
+    /// the entities using them. Models that fail to load keep their last good version.
+    pub async fn reload_models(&mut self, world: &mut World, changed: &[String]) {
+        let Some(mut assets) = world.remove_resource::<AssetServer>() else {
+            return;
+        };
+        let (swapped, errors) = assets.reload(changed).await;
+        world.insert_resource(assets);
+
+        for (mut model, mut collider) in world
+            .query::<(&mut Model, Option<&mut Collider>)>()
+            .iter_mut(world)
+        {
+            swap_model(&swapped, &mut model, collider.as_deref_mut());
+        }
+        // Models kept aside on components rather than drawn, waiting to be used again
+        for mut armament in world.query::<&mut Armament>().iter_mut(world) {
+            for weapon in &mut armament.weapons {
+                if let Some(model) = weapon.model_mut() {
+                    swap_model(&swapped, model, None);
+                }
+            }
+        }
+        for mut respawning in world.query::<&mut Respawning>().iter_mut(world) {
+            let (model, collider) = respawning.parts_mut();
+            swap_model(&swapped, model, Some(collider));
+        }
+
+        if !swapped.is_empty() || !errors.is_empty() {
+            self.report(
+                "models",
+                errors.into_iter().map(|e| e.to_string()).collect(),
+            );
+        }
+    }
+
+    /// Rebuilds a shader whenever one of its two source files in res/shaders/ changed. Returns
+    /// `None` if nothing changed or the new sources failed to build, in which case the caller
+    /// should keep using what it has.
+    pub async fn reload_shader<T>(
+        &mut self,
+        changed: &[String],
+        vertex: &str,
+        fragment: &str,
+        build: impl FnOnce(&str, &str) -> anyhow::Result<T>,
+    ) -> Option<T> {
+        let was_changed = |name: &str| changed.contains(&format!("{}{}", SHADER_FOLDER, name));
+        if !was_changed(vertex) && !was_changed(fragment) {
+            return None;
+        }
+
+        let result = async {
+            let vertex = load_string(vertex, SHADER_FOLDER).await?;
+            let fragment = load_string(fragment, SHADER_FOLDER).await?;
+            build(&vertex, &fragment)
+        }
+        .await;
+
+        match result {
+            Ok(shader) => {
+                self.report(vertex, Vec::new());
+                Some(shader)
+            }
+            Err(e) => {
+                self.report(vertex, vec![e.to_string()]);
+                None
+            }
+        }
+    }
+
+    /// Replaces the errors of one kind of reload, clearing them if there are none.
+    fn report(&mut self, source: &str, errors: Vec<String>) {
+        for error in &errors {
+            warn!("Hot reload failed: {}", error);
+        }
+        if errors.is_empty() {
+            self.errors.remove(source);
+        } else {
+            self.errors.insert(source.to_string(), errors.join("\n"));
+        }
+    }
+
+    pub fn draw_error(&self) {
+        let lines = self
+            .errors
+            .values()
+            .flat_map(|error| error.lines())
+            .collect::<Vec<_>>();
+        for (i, line) in lines.iter().enumerate() {
+            let y = screen_height() - 20.0 * (lines.len() - i) as f32;
+            draw_text(line, 10.0, y, 20.0, RED);
+        }
+    }
+}
+
+/// Replaces the model with its reloaded version if it has one, refitting the collider around it.
+fn swap_model(swapped: &[(Model, Model)], model: &mut Model, collider: Option<&mut Collider>) {
+    let Some((_, new)) = swapped.iter().find(|(old, _)| old.ptr_eq(model)) else {
+        return;
+    };
+    *model = new.clone();
+    if let Some(collider) = collider {
+        *collider = Collider::from_model(new, collider.kind());
+    }
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_files(&path, files);
+        } else {
+            files.push(path);
+        }
+    }
+}